@@ -91,7 +91,7 @@ fn main() {
             .append(true)
             .open(get_out_dir().join("asicam.log"))
             .expect("Error opening log file");
-        let mut drv = GenCamDriverAsi;
+        let mut drv = GenCamDriverAsi::default();
         let num_cameras = drv.available_devices();
         println!("Found {} cameras", num_cameras);
         if num_cameras == 0 {
@@ -117,20 +117,6 @@ fn main() {
         let info = cam.info().expect("Error getting camera info").clone();
         println!("{:?}", info);
 
-        if let Some(color) = info.info.get("Color Sensor") {
-            if let Some(color) = color.as_bool() {
-                if !color {
-                    println!("Setting pixel format to 16-bit");
-                    cam.set_property(
-                        SensorCtrl::PixelFormat.into(),
-                        &GenCamPixelBpp::Bpp16.into(),
-                        false,
-                    )
-                    .expect("Error setting pixel format");
-                }
-            }
-        }
-
         println!("Setting target temperature: {} C", cfg.target_temp);
         if cam
             .set_property(
@@ -1,16 +1,33 @@
 #![warn(missing_docs)]
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, Weak,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use generic_camera::{
-    AnyGenCamInfo, GenCam, GenCamCtrl, GenCamDescriptor, GenCamDriver, GenCamError, GenCamResult,
-    GenCamRoi, GenCamState, Property, PropertyValue,
+    controls::{DeviceCtrl, SensorCtrl},
+    AnyGenCamInfo, GenCam, GenCamCtrl, GenCamDescriptor, GenCamDriver, GenCamError, GenCamPixelBpp,
+    GenCamResult, GenCamRoi, GenCamState, Property, PropertyValue,
 };
-use refimage::GenericImageRef;
+use log::warn;
+use refimage::{GenericImage, GenericImageRef};
 
 use crate::{
-    asihandle::{get_asi_devs, open_device, AsiImager},
-    zwo_ffi::ASIGetNumOfConnectedCameras,
-    zwo_ffi_wrapper::AsiError,
+    asihandle::{
+        describe_device, get_asi_devs, open_device, AsiImager, CameraSettings, CornerStats,
+        DataAlignment, FrameMetadata, FrameStats, GainInfo, Preset, TimestampSource,
+    },
+    zwo_ffi_wrapper::{
+        get_control_value, AsiCameraMode, AsiControlType, AsiError, AsiErrorCode, AsiHandle,
+        ControlUnit, GuideDir, UsbSpeed,
+    },
+    zwo_sdk::sdk,
 };
 
 #[derive(Debug, Default)]
@@ -30,20 +47,179 @@ use crate::{
 ///     let first_device = driver.connect_first_device();
 /// }
 /// ```
-pub struct GenCamDriverAsi;
+pub struct GenCamDriverAsi {
+    default_format: Option<GenCamPixelBpp>,
+    default_cooler_target: Option<f32>,
+    cooler_unmanaged: bool,
+    connected: Vec<Weak<AsiHandle>>,
+    cache: Option<HashMap<i32, Arc<AsiHandle>>>,
+    passive_list: bool,
+}
+
+impl GenCamDriverAsi {
+    /// Set the pixel format applied to cameras right after they are connected.
+    ///
+    /// Without a default format, a connected camera starts in 16-bit mode if it
+    /// is a mono camera that supports it, and in 8-bit mode otherwise.
+    /// Connecting fails if the camera does not support the format.
+    pub fn with_default_format(mut self, fmt: GenCamPixelBpp) -> Self {
+        self.default_format = Some(fmt);
+        self
+    }
+
+    /// Set the cooler target temperature (C) applied to cooled cameras right after
+    /// they are connected.
+    ///
+    /// Setting the target also turns the cooler on. Cameras without a cooler are
+    /// left untouched. The target is rounded to the nearest degree, and connecting
+    /// fails if it is outside the range supported by the camera.
+    pub fn with_default_cooler_target(mut self, target: f32) -> Self {
+        self.default_cooler_target = Some(target);
+        self
+    }
+
+    /// Choose whether the cameras connected through this driver turn their cooler
+    /// off when they are closed.
+    ///
+    /// Cooler management is on by default. Turning it off leaves the cooler as-is
+    /// when a camera is closed, for setups where cooling is managed externally.
+    /// The cooler is still set if requested, e.g. with
+    /// [`with_default_cooler_target`](Self::with_default_cooler_target).
+    pub fn with_cooler_management(mut self, manage: bool) -> Self {
+        self.cooler_unmanaged = !manage;
+        self
+    }
+
+    /// List cameras without opening them.
+    ///
+    /// By default, [`list_devices`](GenCamDriver::list_devices) opens every camera to
+    /// read its serial number, and skips the cameras that fail to open, e.g. because
+    /// another process is using them. Opening a camera that is in use by another
+    /// process may disrupt it, depending on the platform. With passive listing, the
+    /// cameras are not opened: the descriptors have no `"Serial Number"` entry, and
+    /// cameras in use elsewhere are listed, but connecting to them may fail.
+    pub fn with_passive_list(mut self, passive: bool) -> Self {
+        self.passive_list = passive;
+        self
+    }
+
+    /// Keep cameras open after they are dropped, so that reconnecting them is faster.
+    ///
+    /// With the cache enabled, the driver holds on to the handle of every camera it
+    /// connects, and reuses it when the camera is connected again, skipping the SDK
+    /// initialization. As a consequence, dropping a [`GenCamAsi`] no longer closes
+    /// the camera or turns off its cooler; this happens when the driver is dropped.
+    /// Connecting a cached camera again fails while it is still in use, including
+    /// through a [`GenCamInfoAsi`](crate::GenCamInfoAsi) handle. A cached camera must
+    /// not be passed to [`describe`](Self::describe).
+    pub fn with_handle_cache(mut self, enable: bool) -> Self {
+        self.cache = enable.then(HashMap::new);
+        self
+    }
+
+    /// Get the controls of a camera, with their ranges, without connecting to it.
+    ///
+    /// The camera is briefly opened to read its controls, and closed again. This
+    /// must not be called for a camera that is currently connected, since closing
+    /// it invalidates the connected [`GenCamAsi`].
+    pub fn describe(
+        &self,
+        descriptor: &GenCamDescriptor,
+    ) -> GenCamResult<HashMap<GenCamCtrl, Property>> {
+        describe_device(descriptor)
+    }
+
+    /// Connect to the first available camera, without boxing it into an
+    /// [`AnyGenCam`](generic_camera::AnyGenCam).
+    ///
+    /// Avoids dynamic dispatch for applications that only use ASI cameras.
+    pub fn connect_first_concrete(&mut self) -> GenCamResult<GenCamAsi> {
+        let devs = self.list_devices()?;
+        if devs.is_empty() {
+            return Err(GenCamError::NoCamerasAvailable);
+        }
+        self.connect(&devs[0])
+    }
+
+    /// Get the total USB bandwidth (%) allocated to the cameras connected through this driver.
+    ///
+    /// Cameras that have since been dropped are not counted. A total above 100%
+    /// means that the cameras on a shared bus may not be able to run at full speed.
+    pub fn total_bandwidth_percent(&self) -> GenCamResult<i64> {
+        self.connected
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|handle| {
+                get_control_value(handle.handle(), AsiControlType::BWOvld).map(|(bw, _)| bw)
+            })
+            .sum()
+    }
+
+    fn connect(&mut self, descriptor: &GenCamDescriptor) -> GenCamResult<GenCamAsi> {
+        let id = descriptor.id as i32;
+        let cached = self.cache.as_ref().and_then(|cache| cache.get(&id));
+        // the cache holds one reference, any other one is a camera in use
+        if cached.map_or(false, |handle| Arc::strong_count(handle) > 1) {
+            return Err(GenCamError::GeneralError(format!(
+                "Camera {} is already connected",
+                id
+            )));
+        }
+        let mut handle = open_device(descriptor, cached.cloned())?;
+        handle
+            .shared_handle()
+            .set_cooler_management(!self.cooler_unmanaged);
+        let caps = handle.get_concat_caps();
+        if let Some(fmt) = self.default_format {
+            let ctrl: GenCamCtrl = SensorCtrl::PixelFormat.into();
+            let value = PropertyValue::PixelFmt(fmt);
+            if let Some(prop) = caps.get(&ctrl) {
+                prop.validate(&value)
+                    .map_err(|error| GenCamError::PropertyError {
+                        control: ctrl,
+                        error,
+                    })?;
+            }
+            handle.set_property(&ctrl, &value, false)?;
+        }
+        if let Some(target) = self.default_cooler_target {
+            let ctrl: GenCamCtrl = DeviceCtrl::CoolerTemp.into();
+            // only cooled cameras list the cooler target
+            if let Some(prop) = caps.get(&ctrl) {
+                let value = PropertyValue::Int(target.round() as _);
+                prop.validate(&value)
+                    .map_err(|error| GenCamError::PropertyError {
+                        control: ctrl,
+                        error,
+                    })?;
+                handle.set_property(&ctrl, &value, false)?;
+            }
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(id, handle.shared_handle());
+        }
+        self.connected.retain(|handle| handle.strong_count() > 0);
+        self.connected.push(handle.weak_handle());
+        Ok(GenCamAsi {
+            handle,
+            caps,
+            retry: RetryPolicy::default(),
+        })
+    }
+}
 
 impl GenCamDriver for GenCamDriverAsi {
     fn available_devices(&self) -> usize {
-        let res = unsafe { ASIGetNumOfConnectedCameras() };
+        let res = unsafe { sdk().ASIGetNumOfConnectedCameras() };
         res as usize
     }
 
     fn list_devices(&mut self) -> GenCamResult<Vec<generic_camera::GenCamDescriptor>> {
-        get_asi_devs().map_err(|e| match e {
+        get_asi_devs(self.passive_list).map_err(|e| match e {
             AsiError::InvalidId(_, _) => GenCamError::InvalidIndex(0),
             AsiError::CameraRemoved(_, _) => GenCamError::CameraRemoved,
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            _ => GenCamError::GeneralError(e.to_string()),
         })
     }
 
@@ -51,9 +227,7 @@ impl GenCamDriver for GenCamDriverAsi {
         &mut self,
         descriptor: &generic_camera::GenCamDescriptor,
     ) -> GenCamResult<generic_camera::AnyGenCam> {
-        let handle = open_device(descriptor)?;
-        let caps = handle.get_concat_caps();
-        Ok(Box::new(GenCamAsi { handle, caps }))
+        Ok(Box::new(self.connect(descriptor)?))
     }
 
     fn connect_first_device(&mut self) -> GenCamResult<generic_camera::AnyGenCam> {
@@ -69,6 +243,25 @@ impl GenCamDriver for GenCamDriverAsi {
 ///
 /// Implements the [`GenCam`] trait for ASI cameras.
 ///
+/// # Temperature and cooling
+/// The sensor temperature ([`DeviceCtrl::Temperature`]) is reported by all cameras
+/// with a temperature sensor, including cameras without a cooler. The cooler controls
+/// ([`DeviceCtrl::CoolerTemp`], [`DeviceCtrl::CoolerPower`] and
+/// [`DeviceCtrl::CoolerEnable`]) are not listed for uncooled cameras, and return
+/// [`PropertyError::NotFound`] when read or set.
+///
+/// [`DeviceCtrl::Temperature`]: generic_camera::controls::DeviceCtrl::Temperature
+/// [`DeviceCtrl::CoolerTemp`]: generic_camera::controls::DeviceCtrl::CoolerTemp
+/// [`DeviceCtrl::CoolerPower`]: generic_camera::controls::DeviceCtrl::CoolerPower
+/// [`DeviceCtrl::CoolerEnable`]: generic_camera::controls::DeviceCtrl::CoolerEnable
+/// [`PropertyError::NotFound`]: generic_camera::PropertyError::NotFound
+///
+/// # Video mode
+/// This crate only takes single exposures. If the camera was left in video mode
+/// (e.g. by calling the SDK directly), [`start_exposure`](GenCam::start_exposure)
+/// and [`capture`](GenCam::capture) fail with a [`GenCamError::GeneralError`] asking to
+/// stop the video capture first. Video mode is not stopped automatically.
+///
 /// # Examples
 /// ```
 /// use generic_camera::{GenCam, GenCamDriver};
@@ -86,6 +279,692 @@ impl GenCamDriver for GenCamDriverAsi {
 pub struct GenCamAsi {
     handle: AsiImager,
     caps: HashMap<GenCamCtrl, Property>,
+    retry: RetryPolicy,
+}
+
+/// Retry policy used by [`GenCamAsi::capture`](GenCam::capture).
+///
+/// Transient errors ([`GenCamError::ExposureFailed`], [`GenCamError::TimedOut`], and
+/// the SDK's [`AsiErrorCode::InvalidSequence`]) are retried up to `max_attempts` times
+/// in total, waiting `backoff * attempt` between attempts. The failed exposure is
+/// stopped before each retry. All other errors, including
+/// [`GenCamError::CameraRemoved`] and [`GenCamError::CameraClosed`], are returned
+/// immediately.
+///
+/// The default policy makes a single attempt, i.e. does not retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of capture attempts, including the first one.
+    pub max_attempts: u32,
+    /// Base delay between attempts.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Check if an error is transient, and the capture may be retried.
+    ///
+    /// [`AsiErrorCode::InvalidSequence`] is transient, since it reports an exposure
+    /// that was not stopped, and the exposure is stopped before the next attempt.
+    pub fn is_transient(err: &GenCamError) -> bool {
+        matches!(err, GenCamError::ExposureFailed(_) | GenCamError::TimedOut)
+            || AsiErrorCode::from_error(err) == Some(AsiErrorCode::InvalidSequence)
+    }
+}
+
+/// Handle to a background capture loop started with [`GenCamAsi::stream_to_channel`].
+#[derive(Debug)]
+pub struct StreamHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<GenCamAsi>,
+}
+
+impl StreamHandle {
+    /// Check if the capture loop has exited, e.g. because the receiver was dropped.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Stop the capture loop, and return the camera.
+    ///
+    /// An exposure in progress is completed and sent before the loop exits.
+    pub fn stop(self) -> GenCamResult<GenCamAsi> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.thread().unpark();
+        self.thread
+            .join()
+            .map_err(|_| GenCamError::GeneralError("Capture thread panicked".into()))
+    }
+}
+
+impl GenCamAsi {
+    /// Capture frames on a background thread, and send them to `tx`.
+    ///
+    /// A new capture is started every `interval`, or as soon as the previous frame
+    /// has been sent if capturing takes longer. Capture errors are sent as well; the
+    /// loop exits after sending [`GenCamError::CameraRemoved`] or
+    /// [`GenCamError::CameraClosed`], or when the receiver is dropped.
+    ///
+    /// The camera is moved to the capture thread, and is returned by [`StreamHandle::stop`].
+    pub fn stream_to_channel(
+        mut self,
+        tx: Sender<GenCamResult<GenericImage>>,
+        interval: Duration,
+    ) -> StreamHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let start = Instant::now();
+                    let frame = self.capture().map(|img| img.into());
+                    let fatal = matches!(
+                        frame,
+                        Err(GenCamError::CameraRemoved | GenCamError::CameraClosed)
+                    );
+                    if tx.send(frame).is_err() || fatal {
+                        break;
+                    }
+                    while let Some(rem) = interval.checked_sub(start.elapsed()) {
+                        if rem.is_zero() || stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        std::thread::park_timeout(rem);
+                    }
+                }
+                self
+            })
+        };
+        StreamHandle { stop, thread }
+    }
+
+    /// Set the retry policy used by [`capture`](GenCam::capture).
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Get the retry policy used by [`capture`](GenCam::capture).
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Set a `width` x `height` region of interest centered on the sensor, at binning `bin`.
+    ///
+    /// The window is shrunk to a width that is a multiple of 8 and an even height,
+    /// as required by the SDK.
+    pub fn set_centered_roi(
+        &mut self,
+        width: u32,
+        height: u32,
+        bin: u32,
+    ) -> GenCamResult<&GenCamRoi> {
+        self.handle.set_centered_roi(width, height, bin)
+    }
+
+    /// Set the region of interest and the pixel format together.
+    ///
+    /// Both are applied with a single SDK call, so the camera is never left with
+    /// the new ROI and the old format (or vice versa). Unsupported formats return a
+    /// [`PropertyError`](GenCamError::PropertyError) on
+    /// [`SensorCtrl::PixelFormat`](generic_camera::controls::SensorCtrl::PixelFormat).
+    /// A ROI that does not fit the sensor at the current binning returns a
+    /// [`PropertyError`](GenCamError::PropertyError) with
+    /// [`ValueNotSupported`](generic_camera::PropertyError::ValueNotSupported) on
+    /// `SensorCtrl::Custom("ROI")`.
+    ///
+    /// The ROI is read back from the camera after it is set, so
+    /// [`get_roi`](GenCam::get_roi) returns the ROI actually applied.
+    pub fn set_roi_and_format(&mut self, roi: &GenCamRoi, fmt: GenCamPixelBpp) -> GenCamResult<()> {
+        self.handle.set_roi_and_format(roi, fmt)
+    }
+
+    /// Get the unit of the value of a control, e.g. to label it in a UI.
+    ///
+    /// For example, [`DeviceCtrl::Temperature`](generic_camera::controls::DeviceCtrl::Temperature)
+    /// is in [`ControlUnit::Celsius`], and
+    /// [`ExposureCtrl::ExposureTime`](generic_camera::controls::ExposureCtrl::ExposureTime)
+    /// in [`ControlUnit::Microseconds`]. Returns `None` if the camera does not have the control.
+    pub fn control_unit(&self, ctrl: &GenCamCtrl) -> Option<ControlUnit> {
+        self.handle.control_unit(ctrl)
+    }
+
+    /// Get the controls that can be set, i.e. [`list_properties`](GenCam::list_properties)
+    /// without the read-only controls.
+    pub fn writable_properties(&self) -> HashMap<GenCamCtrl, Property> {
+        self.caps
+            .iter()
+            .filter(|(_, prop)| !prop.is_readonly())
+            .map(|(ctrl, prop)| (*ctrl, prop.clone()))
+            .collect()
+    }
+
+    /// Apply several properties, returning the result of each one.
+    ///
+    /// Properties are applied in order, and a failure does not prevent the
+    /// remaining properties from being applied.
+    pub fn set_properties(
+        &mut self,
+        props: &[(GenCamCtrl, PropertyValue, bool)],
+    ) -> Vec<(GenCamCtrl, GenCamResult<()>)> {
+        props
+            .iter()
+            .map(|(ctrl, value, auto)| (*ctrl, self.handle.set_property(ctrl, value, *auto)))
+            .collect()
+    }
+
+    /// Get the pixel formats supported by the camera.
+    ///
+    /// These are also the variants of the
+    /// [`SensorCtrl::PixelFormat`](generic_camera::controls::SensorCtrl::PixelFormat) property.
+    pub fn supported_formats(&self) -> &[GenCamPixelBpp] {
+        self.handle.supported_formats()
+    }
+
+    /// Check whether the camera has a mechanical shutter.
+    ///
+    /// Cameras without a shutter can not take dark frames on their own; the
+    /// [`SensorCtrl::ShutterMode`](generic_camera::controls::SensorCtrl::ShutterMode)
+    /// property is only available on cameras with a shutter.
+    pub fn has_mechanical_shutter(&self) -> bool {
+        self.handle.has_mechanical_shutter()
+    }
+
+    /// Get the image sensor of the camera, e.g. `"IMX533"` for a ZWO ASI533MC Pro.
+    ///
+    /// The sensor is looked up from the camera name in a built-in table, and is also
+    /// reported in the `"Sensor Model"` entry of the camera descriptor. Returns `None`
+    /// for models missing from the table.
+    pub fn sensor_model(&self) -> Option<&'static str> {
+        self.handle.sensor_model()
+    }
+
+    /// Get the USB speed negotiated between the camera and the host.
+    ///
+    /// A USB3 camera plugged into a USB2 port reports [`UsbSpeed::Usb2`], and is
+    /// limited to USB2 throughput. Returns `None` if the camera could not be queried.
+    pub fn usb_connection_speed(&self) -> Option<UsbSpeed> {
+        self.handle.usb_connection_speed()
+    }
+
+    /// Get the current binning factor.
+    pub fn binning(&self) -> u32 {
+        self.handle.binning()
+    }
+
+    /// Change the binning factor, keeping the region of interest on the same area
+    /// of the sensor.
+    ///
+    /// The ROI dimensions and offsets are scaled by the ratio of the old and new
+    /// binning, e.g. a full frame stays a full frame. Returns an error if the camera
+    /// does not support `bin`. [`set_roi`](GenCam::set_roi) keeps the current binning.
+    pub fn set_binning(&mut self, bin: u32) -> GenCamResult<()> {
+        self.handle.set_binning(bin)
+    }
+
+    /// Get the largest region of interest (width, height) at binning `bin`.
+    ///
+    /// The width is a multiple of 8 and the height a multiple of 2, as required by the SDK.
+    /// Returns an error if the camera does not support `bin`.
+    pub fn max_roi_for_bin(&self, bin: u32) -> GenCamResult<(u32, u32)> {
+        self.handle.max_roi_for_bin(bin)
+    }
+
+    /// Read the region of interest from the camera.
+    ///
+    /// Unlike [`get_roi`](GenCam::get_roi), which returns the cached value, this
+    /// queries the SDK, and can be used to verify that a new ROI took effect.
+    pub fn roi_from_device(&self) -> GenCamResult<GenCamRoi> {
+        self.handle.roi_from_device()
+    }
+
+    /// Discard the first frame captured after the ROI or pixel format changes.
+    ///
+    /// The first frame read out after such a change often contains stale data.
+    /// When enabled (the default), [`capture`](GenCam::capture) takes and discards
+    /// an extra frame after a change, unless a frame was already downloaded since.
+    /// This costs one extra exposure at the current exposure time, including the
+    /// first capture after the camera is opened. If the discarded capture fails, the
+    /// next capture tries again. Exposures started with
+    /// [`start_exposure`](GenCam::start_exposure) are not affected.
+    pub fn set_discard_first_after_change(&mut self, discard: bool) {
+        self.handle.set_discard_first_after_change(discard)
+    }
+
+    /// Check if the first frame after a ROI or pixel format change is discarded.
+    pub fn discard_first_after_change(&self) -> bool {
+        self.handle.discard_first_after_change()
+    }
+
+    /// Reset the camera to the full frame at 1x1 binning when it is closed.
+    ///
+    /// Otherwise (the default), the next application to open the camera inherits
+    /// the last region of interest. The pixel format is kept. The reset happens
+    /// when the camera is closed, i.e. once the info handles and the handle cache of
+    /// the driver no longer hold it either.
+    pub fn restore_full_roi_on_drop(&mut self, restore: bool) {
+        self.handle.restore_full_roi_on_drop(restore)
+    }
+
+    /// Capture `n` frames with the current settings, and return their per-pixel average.
+    ///
+    /// Useful e.g. to focus in low light. The mean is returned as 32-bit floating
+    /// point pixels in ADU, without rounding, with the metadata of the first frame.
+    /// Cancelling the exposure through the [info handle](GenCam::info_handle) aborts
+    /// the capture.
+    pub fn capture_averaged(&mut self, n: usize) -> GenCamResult<GenericImage> {
+        self.handle.capture_averaged(n)
+    }
+
+    /// Capture a frame at the given exposure and gain, without changing the camera settings.
+    ///
+    /// `gain` is in raw SDK units (0.1 dB). The exposure and gain in effect before
+    /// the call, including their auto modes, are restored after the capture, even
+    /// if it fails. Useful e.g. for exposure bracketing.
+    pub fn capture_with(&mut self, exposure: Duration, gain: i64) -> GenCamResult<GenericImage> {
+        self.handle.capture_with(exposure, gain)
+    }
+
+    /// Set the exposure time without reading it back from the camera.
+    ///
+    /// Saves an SDK round-trip, e.g. in auto-exposure loops, but trusts that the
+    /// camera applied `exposure` unchanged. Setting
+    /// [`ExposureCtrl::ExposureTime`](generic_camera::controls::ExposureCtrl::ExposureTime)
+    /// through [`set_property`](GenCam::set_property) verifies the value instead.
+    /// Exposures outside the range of the camera are rejected with
+    /// [`PropertyError::ValueNotSupported`](generic_camera::PropertyError::ValueNotSupported).
+    pub fn set_exposure_fast(&mut self, exposure: Duration, auto: bool) -> GenCamResult<()> {
+        self.handle.set_exposure_fast(exposure, auto)
+    }
+
+    /// Get the minimum and maximum exposure times supported by the camera.
+    ///
+    /// Shorthand for the limits of the
+    /// [`ExposureCtrl::ExposureTime`](generic_camera::controls::ExposureCtrl::ExposureTime) property.
+    pub fn exposure_range(&self) -> GenCamResult<(Duration, Duration)> {
+        self.handle.exposure_range()
+    }
+
+    /// Override the maximum exposure time reported by the camera, for cameras whose
+    /// true limit is known to differ from their control caps.
+    ///
+    /// The new maximum applies to [`exposure_range`](Self::exposure_range),
+    /// [`list_properties`](GenCam::list_properties), and the validation of
+    /// [`ExposureCtrl::ExposureTime`](generic_camera::controls::ExposureCtrl::ExposureTime)
+    /// in [`set_property`](GenCam::set_property) and
+    /// [`set_exposure_clamped`](Self::set_exposure_clamped). If the current exposure is
+    /// longer than the new maximum, it is shortened to the maximum. The SDK may still
+    /// clamp exposures above its own limit; the exposure is read back after it is set.
+    ///
+    /// Fails if `max` is below the minimum exposure, or if an exposure is in progress.
+    pub fn set_max_exposure_override(&mut self, max: Duration) -> GenCamResult<()> {
+        self.handle.set_max_exposure_override(max)?;
+        self.caps = self.handle.get_concat_caps();
+        Ok(())
+    }
+
+    /// Set a manual exposure time, clamped to the range supported by the camera.
+    ///
+    /// Unlike [`set_property`](GenCam::set_property), out-of-range exposures are
+    /// not rejected. Returns the exposure time applied by the camera.
+    pub fn set_exposure_clamped(&mut self, exposure: Duration) -> GenCamResult<Duration> {
+        self.handle.set_exposure_clamped(exposure)
+    }
+
+    /// Get the exposure time in microseconds.
+    ///
+    /// Returns the value last read from or written to the camera, without
+    /// querying it.
+    pub fn exposure_micros(&self) -> u64 {
+        self.handle.exposure_micros()
+    }
+
+    /// Set a manual exposure time in microseconds.
+    ///
+    /// Exposures outside the range of the camera are rejected with
+    /// [`PropertyError::ValueNotSupported`](generic_camera::PropertyError::ValueNotSupported).
+    pub fn set_exposure_micros(&mut self, exposure: u64) -> GenCamResult<()> {
+        self.handle.set_exposure_micros(exposure)
+    }
+
+    /// Check whether the camera is in auto-exposure mode.
+    pub fn is_auto_exposure(&self) -> GenCamResult<bool> {
+        self.handle.is_auto_exposure()
+    }
+
+    /// Abort the exposure in progress, discarding any frame that completed but was
+    /// not downloaded.
+    ///
+    /// Unlike [`cancel_capture`](GenCam::cancel_capture), this succeeds when no
+    /// exposure is in progress, leaving the camera idle in any case.
+    pub fn abort_and_drain(&mut self) -> GenCamResult<()> {
+        self.handle.abort_and_drain()
+    }
+
+    /// Wait until the image is ready for download, or `timeout` expires.
+    ///
+    /// Returns `Ok(true)` when the image is ready, `Ok(false)` if the exposure
+    /// was cancelled while waiting, and [`GenCamError::TimedOut`] on timeout.
+    pub fn wait_ready(&self, timeout: Duration) -> GenCamResult<bool> {
+        self.handle.wait_ready(timeout)
+    }
+
+    /// Download the image, reporting progress in `[0.0, 1.0]` through `cb`.
+    ///
+    /// The ASI SDK reads a frame in a single blocking call, so `cb` is invoked
+    /// with `0.0` when the readout starts and `1.0` when it completes.
+    pub fn download_image_with_progress(
+        &mut self,
+        cb: impl Fn(f32),
+    ) -> GenCamResult<GenericImageRef> {
+        self.handle.download_image_with_progress(cb)
+    }
+
+    /// Capture a flat-field frame whose mean is `target_fraction` (0-1, typically
+    /// ~0.5) of the full scale of the sensor.
+    ///
+    /// Frames are captured starting from the current exposure, which is rescaled
+    /// after each frame in proportion to the distance of the mean from the target,
+    /// within the range of the camera and at most `max_exp`. The first frame whose
+    /// mean is within 5% of the target is returned, with its `IMAGETYP` key set to
+    /// `"Flat"`. At most 10 frames are taken; if the target is not reached, e.g.
+    /// because the light source is too dim for `max_exp`, an error is returned.
+    ///
+    /// The exposure and auto-exposure setting are restored afterwards.
+    pub fn capture_flat(
+        &mut self,
+        target_fraction: f32,
+        max_exp: Duration,
+    ) -> GenCamResult<GenericImage> {
+        self.handle.capture_flat(target_fraction, max_exp)
+    }
+
+    /// Download the last exposure as a 32-bit float image, with the pixel values
+    /// normalized to [0, 1].
+    ///
+    /// 8-bit pixels are divided by 255, and 16-bit pixels by the saturation level of
+    /// the sensor, which depends on its bit depth and the [`DataAlignment`]; e.g. a
+    /// saturated pixel of a 12-bit sensor maps to 1.0 with either alignment.
+    /// Use [`GenCam::download_image`] to obtain the raw frame.
+    pub fn download_image_f32(&mut self) -> GenCamResult<GenericImage> {
+        self.handle.download_image_f32()
+    }
+
+    /// Download the last exposure directly into an [`image`] buffer, without going
+    /// through a [`GenericImage`].
+    ///
+    /// RAW8 frames are returned as [`DynamicImage::ImageLuma8`](image::DynamicImage::ImageLuma8),
+    /// and RAW16 frames as [`DynamicImage::ImageLuma16`](image::DynamicImage::ImageLuma16),
+    /// with the dimensions of the ROI. Color frames are the raw Bayer mosaic, and
+    /// must be debayered by the caller. The frame metadata is not attached to the
+    /// buffer; it is available from [`last_frame_metadata`](Self::last_frame_metadata).
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    pub fn download_into_image_buffer(&mut self) -> GenCamResult<image::DynamicImage> {
+        self.handle.download_into_image_buffer()
+    }
+
+    /// Download the last exposure as a single-channel luminance image.
+    ///
+    /// Color (Bayer) frames are debayered and converted to luma; mono frames are
+    /// returned as-is. Use [`GenCam::download_image`] to obtain the raw frame.
+    pub fn download_luma(&mut self) -> GenCamResult<GenericImage> {
+        self.handle.download_luma()
+    }
+
+    /// Re-read the camera properties, and rebuild the descriptor returned by
+    /// [`info`](GenCam::info), e.g. after changing the UUID.
+    ///
+    /// Info handles obtained before the call keep the previous descriptor.
+    pub fn refresh_info(&mut self) -> GenCamResult<&GenCamDescriptor> {
+        self.handle.refresh_info()
+    }
+
+    /// Get the serial number of the camera.
+    pub fn serial_number(&self) -> &str {
+        self.handle.serial_number()
+    }
+
+    /// Check if the camera has a color (Bayer) sensor.
+    pub fn is_color(&self) -> bool {
+        self.handle.is_color()
+    }
+
+    /// Estimate the maximum frame rate for the current ROI and pixel format.
+    ///
+    /// This is a rough estimate based on the USB link speed (USB 2.0 or 3.0) and
+    /// the bandwidth setting only; the exposure time and sensor readout can
+    /// lower the achievable frame rate considerably.
+    pub fn estimated_max_fps(&self) -> f32 {
+        self.handle.estimated_max_fps()
+    }
+
+    /// Compute the mean pixel values in the corners and the center of the last
+    /// downloaded frame, to help detect amplifier glow (experimental).
+    ///
+    /// Returns `None` if no frame has been downloaded yet.
+    pub fn corner_statistics(&self) -> Option<CornerStats> {
+        self.handle.corner_statistics()
+    }
+
+    /// Copy the pixel data of the last downloaded frame as bytes.
+    ///
+    /// 16-bit pixels are in native byte order, or big-endian if `big_endian` is set,
+    /// which avoids a separate byte swap when writing e.g. FITS files.
+    /// Returns `None` if no frame has been downloaded yet.
+    pub fn frame_bytes(&self, big_endian: bool) -> Option<Vec<u8>> {
+        self.handle.frame_bytes(big_endian)
+    }
+
+    /// Compute the minimum, maximum, mean and standard deviation of the pixel values
+    /// of the last downloaded frame, and the fraction of saturated pixels.
+    ///
+    /// Returns `None` if no frame has been downloaded yet, or the frame is empty.
+    pub fn frame_stats(&self) -> Option<FrameStats> {
+        self.handle.frame_stats()
+    }
+
+    /// Check whether more than `threshold_fraction` (0 to 1) of the pixels of the
+    /// last downloaded frame are saturated.
+    ///
+    /// Returns `None` if no frame has been downloaded yet, or the frame is empty.
+    pub fn is_overexposed(&self, threshold_fraction: f32) -> Option<bool> {
+        self.handle.is_overexposed(threshold_fraction)
+    }
+
+    /// Write each downloaded frame to the named shared-memory ring `name`, with `slots` slots,
+    /// so that other processes can read the frames with [`ShmemRing::open`](crate::shmem::ShmemRing::open).
+    ///
+    /// Replaces any ring set up earlier. The ring is removed when streaming is stopped
+    /// or the camera is dropped.
+    #[cfg(feature = "shmem")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "shmem")))]
+    pub fn stream_to_shmem(&mut self, name: &str, slots: usize) -> Result<(), GenCamError> {
+        self.handle.stream_to_shmem(name, slots)
+    }
+
+    /// Stop writing frames to the shared-memory ring, and remove it.
+    #[cfg(feature = "shmem")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "shmem")))]
+    pub fn stop_shmem_stream(&mut self) {
+        self.handle.stop_shmem_stream()
+    }
+
+    /// Get the metadata of the last downloaded frame.
+    ///
+    /// Returns `None` if no frame has been downloaded yet.
+    pub fn last_frame_metadata(&self) -> Option<&FrameMetadata> {
+        self.handle.last_frame_metadata()
+    }
+
+    /// Get the metadata of the last downloaded frame, serialized as JSON.
+    ///
+    /// Returns `None` if no frame has been downloaded yet.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn last_frame_metadata_json(&self) -> Option<String> {
+        self.handle.last_frame_metadata_json()
+    }
+
+    /// Reset `control` to its manufacturer default, as reported by the SDK.
+    ///
+    /// Auto mode is turned off for the control. Returns a
+    /// [`PropertyError`](GenCamError::PropertyError) if the camera does not have the
+    /// control, or if the control is read-only.
+    pub fn reset_to_default(&mut self, control: GenCamCtrl) -> GenCamResult<()> {
+        self.handle.reset_to_default(&control)
+    }
+
+    /// Get the manufacturer default offset, or `None` if the camera has no offset control.
+    pub fn default_offset(&self) -> Option<i64> {
+        self.handle.default_offset()
+    }
+
+    /// Convert a pixel value (ADU) to electrons, using the electrons per ADU
+    /// reported by the SDK.
+    pub fn adu_to_electrons(&self, adu: f64) -> f64 {
+        self.handle.adu_to_electrons(adu)
+    }
+
+    /// Estimate the dynamic range of the sensor, in stops, for SNR planning.
+    ///
+    /// The full well is approximated as e-/ADU x 2^bitdepth, using the electrons per
+    /// ADU reported by the SDK, and the noise floor as one electron or one ADU,
+    /// whichever is larger; read noise is not accounted for. For example, a 12-bit
+    /// sensor at 0.25 e-/ADU has a full well of ~1000 e- and ~10 stops of dynamic range.
+    ///
+    /// Returns `None` if the SDK does not report the bit depth or electrons per ADU.
+    pub fn dynamic_range_stops(&self) -> Option<f32> {
+        self.handle.dynamic_range_stops()
+    }
+
+    /// Set the clock used to timestamp frames, starting with the next exposure.
+    pub fn set_timestamp_source(&mut self, source: TimestampSource) {
+        self.handle.set_timestamp_source(source)
+    }
+
+    /// Get the clock used to timestamp frames.
+    pub fn timestamp_source(&self) -> TimestampSource {
+        self.handle.timestamp_source()
+    }
+
+    /// Set the alignment of 16-bit images returned by [`download_image`](GenCam::download_image).
+    ///
+    /// The SDK left-shifts pixel values to fill 16 bits; with
+    /// [`DataAlignment::RightAligned`] they are shifted back to the sensor bit depth.
+    /// 8-bit images are not affected.
+    pub fn set_data_alignment(&mut self, alignment: DataAlignment) {
+        self.handle.set_data_alignment(alignment)
+    }
+
+    /// Get the alignment of 16-bit images.
+    pub fn data_alignment(&self) -> DataAlignment {
+        self.handle.data_alignment()
+    }
+
+    /// Get the current gain as a raw SDK value, in dB, and as a percentage
+    /// of the gain range, along with the auto gain state.
+    pub fn gain_info(&self) -> GenCamResult<GainInfo> {
+        self.handle.gain_info()
+    }
+
+    /// Set the auto-exposure target brightness as a percentage (0-100) of its range.
+    ///
+    /// The percentage is converted to the raw range of
+    /// [`ExposureCtrl::AutoTargetBrightness`](generic_camera::controls::ExposureCtrl::AutoTargetBrightness),
+    /// and can be changed during an exposure.
+    pub fn set_auto_target_percent(&mut self, percent: f32) -> GenCamResult<()> {
+        self.handle.set_auto_target_percent(percent)
+    }
+
+    /// Get the auto-exposure target brightness as a percentage (0-100) of its range.
+    pub fn auto_target_percent(&self) -> GenCamResult<f32> {
+        self.handle.auto_target_percent()
+    }
+
+    /// Take a snapshot of the current camera settings.
+    pub fn export_settings(&self) -> GenCamResult<CameraSettings> {
+        self.handle.export_settings()
+    }
+
+    /// Get the auto state of every control that supports auto mode, e.g.
+    /// exposure and gain, read from the camera in one pass.
+    ///
+    /// Controls without auto mode are not included.
+    pub fn auto_states(&self) -> GenCamResult<HashMap<GenCamCtrl, bool>> {
+        self.handle.auto_states()
+    }
+
+    /// Apply previously exported camera settings.
+    ///
+    /// Every setting is validated against the camera's limits. If any setting
+    /// fails to apply, the settings in effect before the call are restored and
+    /// the error is returned.
+    pub fn import_settings(&mut self, settings: &CameraSettings) -> GenCamResult<()> {
+        self.handle.import_settings(settings)
+    }
+
+    /// Apply the controls of a [`Preset`], leaving all other controls untouched.
+    ///
+    /// If any control fails to apply, the controls already changed are restored
+    /// to their previous values and the error is returned.
+    pub fn apply_preset(&mut self, preset: &Preset) -> GenCamResult<()> {
+        self.handle.apply_preset(preset)
+    }
+
+    /// Get the camera modes supported by a trigger-capable camera.
+    ///
+    /// Returns [`GenCamError::InvalidControlType`] if the camera does not support triggering.
+    pub fn supported_modes(&self) -> GenCamResult<Vec<AsiCameraMode>> {
+        self.handle.supported_modes()
+    }
+
+    /// Get the current camera mode. Cameras without trigger support are always in
+    /// [`AsiCameraMode::Normal`].
+    pub fn get_mode(&self) -> GenCamResult<AsiCameraMode> {
+        self.handle.get_mode()
+    }
+
+    /// Set the camera mode.
+    ///
+    /// Returns [`GenCamError::InvalidControlType`] if the camera does not support triggering,
+    /// or if the mode is not supported by the camera.
+    pub fn set_mode(&mut self, mode: AsiCameraMode) -> GenCamResult<()> {
+        self.handle.set_mode(mode)
+    }
+
+    /// Issue an ST4 guide pulse in direction `dir` for `duration`.
+    ///
+    /// This call does not block: the pulse is started, and stopped from a
+    /// background thread once `duration` has elapsed. The duration must be
+    /// non-zero and at most [`max_pulse_duration`](GenCamAsi::max_pulse_duration).
+    /// A new pulse in the same direction extends the pulse in progress, which is
+    /// then ended by the new pulse only.
+    ///
+    /// Returns [`GenCamError::InvalidControlType`] if the camera has no ST4 port, and
+    /// a [`GenCamError::PropertyError`] with
+    /// [`PropertyError::ValueNotSupported`](generic_camera::PropertyError::ValueNotSupported)
+    /// if the duration is out of range.
+    pub fn pulse_guide(&self, dir: GuideDir, duration: Duration) -> GenCamResult<()> {
+        self.handle.pulse_guide(dir, duration)
+    }
+
+    /// Set the maximum allowed guide pulse duration (10 s by default).
+    pub fn set_max_pulse_duration(&mut self, max: Duration) {
+        self.handle.set_max_pulse_duration(max)
+    }
+
+    /// Get the maximum allowed guide pulse duration.
+    pub fn max_pulse_duration(&self) -> Duration {
+        self.handle.max_pulse_duration()
+    }
 }
 
 impl GenCam for GenCamAsi {
@@ -143,11 +1022,23 @@ impl GenCam for GenCamAsi {
     }
 
     fn capture(&mut self) -> GenCamResult<GenericImageRef> {
-        let (exp, _) = self.handle.get_exposure()?;
-        self.handle.start_exposure()?;
-        std::thread::sleep(exp);
-        while !self.handle.image_ready()? {
-            std::thread::sleep(Duration::from_millis(10));
+        if self.handle.needs_discard() {
+            // the first frame after a ROI or format change is often stale
+            self.handle.expose()?;
+            self.handle.download_image()?;
+        }
+        let mut attempt = 1;
+        while let Err(e) = self.handle.expose() {
+            if attempt >= self.retry.max_attempts || !RetryPolicy::is_transient(&e) {
+                return Err(e);
+            }
+            warn!("Capture attempt {} failed: {:?}, retrying", attempt, e);
+            // the failed exposure may still be running on the camera
+            if let Err(e) = self.handle.abort_and_drain() {
+                warn!("Could not stop the failed exposure: {:?}", e);
+            }
+            std::thread::sleep(self.retry.backoff * attempt);
+            attempt += 1;
         }
         self.handle.download_image()
     }
@@ -168,3 +1059,476 @@ impl GenCam for GenCamAsi {
         self.handle.get_property(&name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use generic_camera::{
+        controls::{AnalogCtrl, ExposureCtrl},
+        PropertyError,
+    };
+
+    use super::*;
+    use crate::zwo_ffi::{
+        ASI_BOOL_ASI_FALSE, ASI_CONTROL_TYPE_ASI_AUTO_TARGET_BRIGHTNESS,
+        ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD, ASI_CONTROL_TYPE_ASI_COOLER_ON,
+        ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC, ASI_CONTROL_TYPE_ASI_GAIN,
+        ASI_CONTROL_TYPE_ASI_TARGET_TEMP, ASI_CONTROL_TYPE_ASI_TEMPERATURE,
+        ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED, ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE,
+        ASI_EXPOSURE_STATUS_ASI_EXP_FAILED,
+    };
+    use crate::zwo_sdk::mock;
+
+    fn connect_mock() -> GenCamAsi {
+        let mut drv = GenCamDriverAsi::default();
+        let dev = drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .remove(0);
+        let mut cam = drv.connect(&dev).expect("Could not connect to camera");
+        cam.set_discard_first_after_change(false);
+        cam.set_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+        });
+        cam
+    }
+
+    #[test]
+    fn transient_errors() {
+        let sdk_error = |e: AsiError| GenCamError::GeneralError(e.to_string());
+        assert!(RetryPolicy::is_transient(&GenCamError::ExposureFailed(
+            "".into()
+        )));
+        assert!(RetryPolicy::is_transient(&GenCamError::TimedOut));
+        assert!(RetryPolicy::is_transient(&sdk_error(
+            AsiError::InvalidSequence(Some("ASIStartExposure".into()), None)
+        )));
+        assert!(!RetryPolicy::is_transient(&sdk_error(
+            AsiError::GeneralError(Some("ASIStartExposure".into()), None)
+        )));
+        assert!(!RetryPolicy::is_transient(&GenCamError::CameraClosed));
+        assert!(!RetryPolicy::is_transient(&GenCamError::CameraRemoved));
+        assert!(!RetryPolicy::is_transient(&GenCamError::ExposureInProgress));
+    }
+
+    #[test]
+    fn capture_retries_transient_error() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        sdk.fail(
+            "ASIStartExposure",
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE,
+        );
+        cam.capture().expect("Capture was not retried");
+        assert_eq!(sdk.calls("ASIStartExposure"), 2);
+        assert_eq!(sdk.calls("ASIStopExposure"), 1);
+    }
+
+    #[test]
+    fn capture_gives_up_after_max_attempts() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        sdk.camera().outcome = ASI_EXPOSURE_STATUS_ASI_EXP_FAILED;
+        assert!(matches!(cam.capture(), Err(GenCamError::ExposureFailed(_))));
+        assert_eq!(sdk.calls("ASIStartExposure"), 3);
+        // stopped before each retry
+        assert_eq!(sdk.calls("ASIStopExposure"), 2);
+    }
+
+    #[test]
+    fn capture_does_not_retry_fatal_error() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        sdk.fail("ASIStartExposure", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(cam.capture(), Err(GenCamError::CameraClosed)));
+        assert_eq!(sdk.calls("ASIStartExposure"), 1);
+        assert_eq!(sdk.calls("ASIStopExposure"), 0);
+    }
+
+    #[test]
+    fn capture_discards_first_frame_after_change() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        cam.set_discard_first_after_change(true);
+        // the first capture after opening the camera is discarded
+        cam.capture().expect("Could not capture");
+        assert_eq!(sdk.calls("ASIStartExposure"), 2);
+        cam.capture().expect("Could not capture");
+        assert_eq!(sdk.calls("ASIStartExposure"), 3);
+        let roi = GenCamRoi {
+            x_min: 0,
+            y_min: 0,
+            width: 32,
+            height: 24,
+        };
+        cam.set_roi(&roi).expect("Could not set ROI");
+        cam.capture().expect("Could not capture");
+        assert_eq!(sdk.calls("ASIStartExposure"), 5);
+    }
+
+    #[test]
+    fn capture_retries_failed_discard() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        cam.set_discard_first_after_change(true);
+        sdk.fail("ASIStartExposure", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(cam.capture(), Err(GenCamError::CameraClosed)));
+        // the frame still has to be discarded
+        cam.capture().expect("Could not capture");
+        assert_eq!(sdk.calls("ASIStartExposure"), 3);
+        cam.capture().expect("Could not capture");
+        assert_eq!(sdk.calls("ASIStartExposure"), 4);
+    }
+
+    fn cached_driver() -> (GenCamDriverAsi, GenCamDescriptor) {
+        let mut drv = GenCamDriverAsi::default().with_handle_cache(true);
+        let dev = drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .remove(0);
+        (drv, dev)
+    }
+
+    #[test]
+    fn cached_reconnect_keeps_camera_open() {
+        let sdk = mock::install();
+        let (mut drv, dev) = cached_driver();
+        let cam = drv.connect(&dev).expect("Could not connect to camera");
+        drop(cam);
+        assert!(sdk.camera().open);
+        let cam = drv.connect(&dev).expect("Could not reconnect to camera");
+        assert_eq!(sdk.calls("ASICloseCamera"), 0);
+        assert_eq!(sdk.calls("ASIInitCamera"), 1);
+        drop(cam);
+        drop(drv);
+        assert_eq!(sdk.calls("ASICloseCamera"), 1);
+    }
+
+    #[test]
+    fn cached_camera_in_use() {
+        let sdk = mock::install();
+        let (mut drv, dev) = cached_driver();
+        let cam = drv.connect(&dev).expect("Could not connect to camera");
+        assert!(matches!(
+            drv.connect(&dev),
+            Err(GenCamError::GeneralError(_))
+        ));
+        let info = cam.info_handle();
+        drop(cam);
+        assert!(drv.connect(&dev).is_err());
+        drop(info);
+        drv.connect(&dev).expect("Could not reconnect to camera");
+        assert_eq!(sdk.calls("ASIInitCamera"), 1);
+    }
+
+    #[test]
+    fn cache_skips_failed_connect() {
+        let sdk = mock::install();
+        let (drv, dev) = cached_driver();
+        // outside the -40..=30 range of the mock
+        let mut drv = drv.with_default_cooler_target(50.0);
+        assert!(drv.connect(&dev).is_err());
+        assert!(drv.cache.as_ref().map_or(false, HashMap::is_empty));
+        assert!(!sdk.camera().open);
+    }
+
+    #[test]
+    fn writable_properties_skip_readonly() {
+        let sdk = mock::install();
+        // read-only on real cameras
+        sdk.camera()
+            .controls
+            .iter_mut()
+            .filter(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC)
+            .for_each(|(caps, _, _)| caps.IsWritable = ASI_BOOL_ASI_FALSE);
+        let cam = connect_mock();
+        let writable = cam.writable_properties();
+        let temperature: GenCamCtrl = DeviceCtrl::Temperature.into();
+        let power: GenCamCtrl = DeviceCtrl::CoolerPower.into();
+        assert!(cam.list_properties().contains_key(&temperature));
+        assert!(cam.list_properties().contains_key(&power));
+        assert!(!writable.contains_key(&temperature));
+        assert!(!writable.contains_key(&power));
+        assert!(writable.contains_key(&DeviceCtrl::CoolerTemp.into()));
+        assert!(writable.contains_key(&ExposureCtrl::ExposureTime.into()));
+        assert_eq!(
+            writable.len(),
+            cam.list_properties()
+                .values()
+                .filter(|prop| !prop.is_readonly())
+                .count()
+        );
+    }
+
+    #[test]
+    fn set_properties_reports_each_result() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        let gain: GenCamCtrl = AnalogCtrl::Gain.into();
+        let target: GenCamCtrl = ExposureCtrl::AutoTargetBrightness.into();
+        let temperature: GenCamCtrl = DeviceCtrl::Temperature.into();
+        let res = cam.set_properties(&[
+            (gain, PropertyValue::Float(15.0), false),
+            // out of range
+            (target, PropertyValue::Int(1000), false),
+            // read-only
+            (temperature, PropertyValue::Float(-10.0), false),
+            (target, PropertyValue::Int(120), false),
+        ]);
+        let ctrls: Vec<_> = res.iter().map(|(ctrl, _)| *ctrl).collect();
+        assert_eq!(ctrls, vec![gain, target, temperature, target]);
+        assert!(res[0].1.is_ok());
+        assert!(matches!(
+            res[1].1,
+            Err(GenCamError::PropertyError {
+                error: PropertyError::ValueNotSupported,
+                ..
+            })
+        ));
+        assert!(res[2].1.is_err());
+        assert!(res[3].1.is_ok());
+        // the failures leave the camera as it was, and do not stop the batch
+        assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_GAIN), Some(150));
+        assert_eq!(
+            sdk.camera()
+                .value(ASI_CONTROL_TYPE_ASI_AUTO_TARGET_BRIGHTNESS),
+            Some(120)
+        );
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_TEMPERATURE),
+            Some(200)
+        );
+    }
+
+    #[test]
+    fn total_bandwidth_of_connected_cameras() {
+        let sdk = mock::install();
+        let mut drv = GenCamDriverAsi::default();
+        assert_eq!(drv.total_bandwidth_percent().ok(), Some(0));
+        let dev = drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .remove(0);
+        let cam = drv.connect(&dev).expect("Could not connect to camera");
+        assert_eq!(drv.total_bandwidth_percent().ok(), Some(50));
+        sdk.camera()
+            .set_value(ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD, 80);
+        assert_eq!(drv.total_bandwidth_percent().ok(), Some(80));
+        // the mock has a single camera, connect it twice
+        let other = drv.connect(&dev).expect("Could not connect to camera");
+        assert_eq!(drv.total_bandwidth_percent().ok(), Some(160));
+        drop(other);
+        drop(cam);
+        assert_eq!(drv.total_bandwidth_percent().ok(), Some(0));
+    }
+
+    #[test]
+    fn connect_first_concrete_camera() {
+        let sdk = mock::install();
+        let mut drv = GenCamDriverAsi::default();
+        // the only camera can not be read
+        sdk.fail(
+            "ASIGetCameraProperty",
+            ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED,
+        );
+        assert!(matches!(
+            drv.connect_first_concrete(),
+            Err(GenCamError::NoCamerasAvailable)
+        ));
+        assert_eq!(sdk.calls("ASIOpenCamera"), 0);
+        let cam = drv
+            .connect_first_concrete()
+            .expect("Could not connect to camera");
+        assert_eq!(cam.camera_name(), "ZWO ASI120MM Mini");
+        assert!(sdk.camera().open);
+    }
+
+    #[test]
+    fn describe_without_connecting() {
+        let sdk = mock::install();
+        let mut drv = GenCamDriverAsi::default().with_passive_list(true);
+        let dev = drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .remove(0);
+        let props = drv.describe(&dev).expect("Could not describe camera");
+        let gain = props
+            .get(&AnalogCtrl::Gain.into())
+            .expect("Gain is not listed");
+        assert_eq!(gain.get_max().ok(), Some(PropertyValue::Float(30.0)));
+        assert!(props
+            .get(&DeviceCtrl::Temperature.into())
+            .map_or(false, Property::is_readonly));
+        assert!(props.contains_key(&SensorCtrl::PixelFormat.into()));
+        assert_eq!(sdk.calls("ASIInitCamera"), 0);
+        assert!(!sdk.camera().open);
+        // the camera is closed on failure too
+        sdk.fail(
+            "ASIGetNumOfControls",
+            ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED,
+        );
+        assert!(drv.describe(&dev).is_err());
+        assert_eq!(sdk.calls("ASICloseCamera"), 2);
+        assert!(!sdk.camera().open);
+    }
+
+    #[test]
+    fn passive_list_does_not_open_cameras() {
+        let sdk = mock::install();
+        let mut drv = GenCamDriverAsi::default();
+        let devs = drv.list_devices().expect("Could not list cameras");
+        assert!(devs[0].info.contains_key("Serial Number"));
+        assert_eq!(sdk.calls("ASIOpenCamera"), 1);
+        // e.g. in use by another process
+        sdk.fail("ASIOpenCamera", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .is_empty());
+        let mut drv = drv.with_passive_list(true);
+        let calls = sdk.calls("ASIOpenCamera");
+        let devs = drv.list_devices().expect("Could not list cameras");
+        assert_eq!(devs.len(), 1);
+        assert!(!devs[0].info.contains_key("Serial Number"));
+        assert_eq!(sdk.calls("ASIOpenCamera"), calls);
+        // connecting opens the camera
+        drv.connect(&devs[0]).expect("Could not connect to camera");
+        assert_eq!(sdk.calls("ASIOpenCamera"), calls + 1);
+    }
+
+    #[test]
+    fn default_cooler_target_on_connect() {
+        {
+            let sdk = mock::install();
+            let mut drv = GenCamDriverAsi::default().with_default_cooler_target(-10.4);
+            let dev = drv
+                .list_devices()
+                .expect("Could not list cameras")
+                .remove(0);
+            let _cam = drv.connect(&dev).expect("Could not connect to camera");
+            assert_eq!(
+                sdk.camera().value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP),
+                Some(-10)
+            );
+            assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_COOLER_ON), Some(1));
+        }
+        {
+            // uncooled cameras are left untouched
+            let sdk = mock::install();
+            {
+                let mut camera = sdk.camera();
+                camera.info.IsCoolerCam = ASI_BOOL_ASI_FALSE;
+                camera.controls.retain(|(caps, _, _)| {
+                    ![
+                        ASI_CONTROL_TYPE_ASI_TARGET_TEMP,
+                        ASI_CONTROL_TYPE_ASI_COOLER_ON,
+                        ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC,
+                    ]
+                    .contains(&caps.ControlType)
+                });
+            }
+            let mut drv = GenCamDriverAsi::default();
+            let dev = drv
+                .list_devices()
+                .expect("Could not list cameras")
+                .remove(0);
+            let calls = sdk.calls("ASISetControlValue");
+            let cam = drv.connect(&dev).expect("Could not connect to camera");
+            let plain = sdk.calls("ASISetControlValue") - calls;
+            drop(cam);
+            let mut drv = drv.with_default_cooler_target(-10.0);
+            let calls = sdk.calls("ASISetControlValue");
+            let _cam = drv.connect(&dev).expect("Could not connect to camera");
+            assert_eq!(sdk.calls("ASISetControlValue") - calls, plain);
+        }
+    }
+
+    #[test]
+    fn unmanaged_cooler_left_on() {
+        let sdk = mock::install();
+        let mut drv = GenCamDriverAsi::default().with_default_cooler_target(-10.0);
+        let dev = drv
+            .list_devices()
+            .expect("Could not list cameras")
+            .remove(0);
+        let cam = drv.connect(&dev).expect("Could not connect to camera");
+        drop(cam);
+        // turned off when the camera is closed
+        assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_COOLER_ON), Some(0));
+        let mut drv = drv.with_cooler_management(false);
+        let cam = drv.connect(&dev).expect("Could not connect to camera");
+        // the default target is still applied
+        assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_COOLER_ON), Some(1));
+        let calls = sdk.calls("ASISetControlValue");
+        drop(cam);
+        assert_eq!(sdk.calls("ASISetControlValue"), calls);
+        assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_COOLER_ON), Some(1));
+        assert!(!sdk.camera().open);
+    }
+
+    #[test]
+    fn stream_sends_frames_until_stopped() {
+        let sdk = mock::install();
+        let cam = connect_mock();
+        let (tx, rx) = mpsc::channel();
+        let stream = cam.stream_to_channel(tx, Duration::ZERO);
+        for _ in 0..3 {
+            let img = rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("No frame received")
+                .expect("Could not capture");
+            assert_eq!((img.width(), img.height()), (64, 48));
+        }
+        let mut cam = stream.stop().expect("Could not stop stream");
+        // the frames captured before the loop exited, then the end of the stream
+        assert!(rx.iter().all(|frame| frame.is_ok()));
+        let calls = sdk.calls("ASIStartExposure");
+        assert!(calls >= 3);
+        assert!(!cam.is_capturing());
+        cam.capture().expect("Could not capture after streaming");
+        assert_eq!(sdk.calls("ASIStartExposure"), calls + 1);
+        // dropping the receiver ends the stream
+        let (tx, rx) = mpsc::channel();
+        let stream = cam.stream_to_channel(tx, Duration::ZERO);
+        drop(rx);
+        let start = Instant::now();
+        while !stream.is_finished() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "Stream did not end"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        stream.stop().expect("Could not stop stream");
+    }
+
+    #[test]
+    fn capture_waits_for_exposure() {
+        let sdk = mock::install();
+        let mut cam = connect_mock();
+        let exposure = Duration::from_millis(100);
+        cam.set_exposure_micros(exposure.as_micros() as _)
+            .expect("Could not set exposure");
+        let start = Instant::now();
+        cam.capture().expect("Could not capture");
+        assert!(start.elapsed() >= exposure);
+        // cancelled while exposing
+        let info = cam.info_handle().expect("No info handle");
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                while !info.is_capturing() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                info.cancel_capture().expect("Could not cancel capture");
+            });
+            assert!(matches!(
+                cam.capture(),
+                Err(GenCamError::ExposureNotStarted)
+            ));
+        });
+        assert_eq!(sdk.calls("ASIStartExposure"), 2);
+        assert!(!cam.is_capturing());
+    }
+}
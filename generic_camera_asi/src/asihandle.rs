@@ -9,38 +9,42 @@ use std::{
     hash::Hash,
     mem::MaybeUninit,
     sync::{
-        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
-        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
     },
     thread::sleep,
     time::{Duration, Instant, SystemTime},
 };
 
 use atomic_time::AtomicOptionInstant;
-use refimage::{BayerPattern, BayerShift, GenericImageRef, EXPOSURE_KEY};
+use refimage::{
+    BayerPattern, BayerShift, DemosaicMethod, GenericImageRef, ImageProps, ToLuma, EXPOSURE_KEY,
+};
 
 use crate::{
     zwo_ffi::{
-        ASICloseCamera, ASIGetCameraProperty, ASIGetCameraPropertyByID, ASIGetControlCaps,
-        ASIGetControlValue, ASIGetDataAfterExp, ASIGetExpStatus, ASIGetID,
-        ASIGetNumOfConnectedCameras, ASIGetNumOfControls, ASIGetSerialNumber, ASIInitCamera,
-        ASIOpenCamera, ASISetControlValue, ASISetID, ASIStartExposure, ASIStopExposure,
         ASI_BAYER_PATTERN_ASI_BAYER_BG, ASI_BAYER_PATTERN_ASI_BAYER_GB,
         ASI_BAYER_PATTERN_ASI_BAYER_GR, ASI_BAYER_PATTERN_ASI_BAYER_RG, ASI_BOOL_ASI_FALSE,
-        ASI_BOOL_ASI_TRUE, ASI_CAMERA_INFO, ASI_CONTROL_CAPS, ASI_CONTROL_TYPE_ASI_COOLER_ON,
-        ASI_CONTROL_TYPE_ASI_FLIP, ASI_FLIP_STATUS_ASI_FLIP_BOTH, ASI_FLIP_STATUS_ASI_FLIP_HORIZ,
-        ASI_FLIP_STATUS_ASI_FLIP_NONE, ASI_FLIP_STATUS_ASI_FLIP_VERT, ASI_ID, ASI_IMG_TYPE,
-        ASI_IMG_TYPE_ASI_IMG_END, ASI_IMG_TYPE_ASI_IMG_RAW16, ASI_IMG_TYPE_ASI_IMG_RAW8,
+        ASI_BOOL_ASI_TRUE, ASI_CAMERA_INFO, ASI_CAMERA_MODE_ASI_MODE_END, ASI_CONTROL_CAPS,
+        ASI_CONTROL_TYPE_ASI_COOLER_ON, ASI_CONTROL_TYPE_ASI_FLIP, ASI_FLIP_STATUS_ASI_FLIP_BOTH,
+        ASI_FLIP_STATUS_ASI_FLIP_HORIZ, ASI_FLIP_STATUS_ASI_FLIP_NONE,
+        ASI_FLIP_STATUS_ASI_FLIP_VERT, ASI_ID, ASI_IMG_TYPE, ASI_IMG_TYPE_ASI_IMG_END,
+        ASI_IMG_TYPE_ASI_IMG_RAW16, ASI_IMG_TYPE_ASI_IMG_RAW8, ASI_SUPPORTED_MODE,
     },
     zwo_ffi_wrapper::{
         get_bins, get_caps, get_control_caps, get_control_value, get_info, get_pixfmt,
-        get_split_ctrl, map_control_cap, set_control_value, string_from_char, to_asibool,
-        AsiControlType, AsiCtrl, AsiDeviceCtrl, AsiError, AsiExposureStatus, AsiHandle, AsiRoi,
-        AsiSensorCtrl,
+        get_split_ctrl, get_uuid, map_control_cap, sensor_model, set_control_value,
+        string_from_char, to_asibool, with_code, AsiCameraMode, AsiControlType, AsiCtrl,
+        AsiDeviceCtrl, AsiError, AsiExposureStatus, AsiHandle, AsiRoi, AsiSensorCtrl, ControlUnit,
+        GuideDir, UsbSpeed,
     },
-    ASICALL,
+    zwo_sdk::sdk,
+    ASICALL, ASICALL_RETRY,
 };
 
+#[cfg(feature = "shmem")]
+use crate::shmem::ShmemRing;
+
 use generic_camera::{
     controls::AnalogCtrl, controls::CustomName, controls::DeviceCtrl, controls::DigitalIoCtrl,
     controls::ExposureCtrl, controls::SensorCtrl, GenCam, GenCamCtrl, GenCamInfo, GenCamResult,
@@ -53,9 +57,13 @@ use generic_camera::{
 
 use log::warn;
 use refimage::ColorSpace;
-use refimage::{DynamicImageRef, ImageRef};
+use refimage::{DynamicImageOwned, DynamicImageRef, ImageOwned, ImageRef};
 
-pub(crate) fn get_asi_devs() -> Result<Vec<GenCamDescriptor>, AsiError> {
+/// List the connected cameras.
+///
+/// Unless `passive` is set, each camera is opened to read its serial number, and
+/// cameras that fail to open (e.g. in use by another process) are skipped.
+pub(crate) fn get_asi_devs(passive: bool) -> Result<Vec<GenCamDescriptor>, AsiError> {
     fn get_sn(handle: i32) -> Option<String> {
         let mut sn = ASI_ID::default();
         ASICALL!(ASIGetSerialNumber(handle, &mut sn as _)).ok()?;
@@ -66,13 +74,17 @@ pub(crate) fn get_asi_devs() -> Result<Vec<GenCamDescriptor>, AsiError> {
         Some(sn)
     }
 
-    let num_cameras = unsafe { ASIGetNumOfConnectedCameras() };
+    let num_cameras = unsafe { sdk().ASIGetNumOfConnectedCameras() };
     let mut devs = Vec::with_capacity(num_cameras as _);
     for id in 0..num_cameras {
         let mut dev = ASI_CAMERA_INFO::default();
         if ASICALL!(ASIGetCameraProperty(&mut dev, id)).is_err() {
             continue;
         }
+        if passive {
+            devs.push(dev.into());
+            continue;
+        }
         if ASICALL!(ASIOpenCamera(dev.CameraID)).is_err() {
             continue;
         }
@@ -96,6 +108,68 @@ fn get_sn(handle: i32) -> Result<[u8; 16], AsiError> {
     Ok(out)
 }
 
+fn uuid_ctrl() -> GenCamCtrl {
+    DeviceCtrl::Custom("UUID".into()).into()
+}
+
+/// Control reported when a region of interest does not fit the sensor.
+fn roi_ctrl() -> GenCamCtrl {
+    SensorCtrl::Custom("ROI".into()).into()
+}
+
+/// Error for an interior-mutable field that could not be borrowed.
+///
+/// [`GenCamError::AccessViolation`] does not carry any context, so the name of
+/// the field is reported in a [`GenCamError::GeneralError`] instead.
+fn access_violation(src: &str) -> GenCamError {
+    GenCamError::GeneralError(format!("AccessViolation: could not borrow `{}`", src))
+}
+
+/// Approximate usable USB 3.0 throughput (bytes/s) at 100% bandwidth.
+const USB3_BYTES_PER_SEC: f32 = 380e6;
+/// Approximate usable USB 2.0 throughput (bytes/s) at 100% bandwidth.
+const USB2_BYTES_PER_SEC: f32 = 43e6;
+
+/// Default upper bound on the duration of an ST4 guide pulse.
+pub(crate) const MAX_PULSE_DURATION: Duration = Duration::from_secs(10);
+
+/// Cooler power (%) at which the duty-cycle limit is disabled.
+const COOLER_POWER_UNLIMITED: i64 = 100;
+/// Interval between cooler power checks when a duty-cycle limit is set.
+const COOLER_MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+/// Step (C) by which the target temperature is raised when the limit is exceeded.
+const COOLER_BACKOFF_STEP: i64 = 1;
+/// Interval between temperature checks while waiting for the sensor to stabilize.
+const TEMPERATURE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of attempts at reading out a completed frame before giving up.
+const DOWNLOAD_RETRY_ATTEMPTS: usize = 3;
+/// Delay between frame readout attempts.
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Maximum number of frames taken while adjusting the exposure of a flat frame.
+const FLAT_MAX_ITERATIONS: usize = 10;
+/// Tolerance on the mean of a flat frame, relative to the target.
+const FLAT_TOLERANCE: f64 = 0.05;
+/// Largest factor by which the exposure of a flat frame is changed in one step.
+const FLAT_MAX_STEP: f64 = 10.0;
+
+/// Cooler power limit, shared with the cooler monitor thread.
+#[derive(Debug)]
+pub(crate) struct CoolerLimit {
+    /// Maximum sustained cooler power (%).
+    percent: AtomicI64,
+    /// Incremented whenever a monitor is started, so that older monitors exit.
+    generation: AtomicU64,
+}
+
+impl Default for CoolerLimit {
+    fn default() -> Self {
+        Self {
+            percent: AtomicI64::new(COOLER_POWER_UNLIMITED),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct LastExposureInfo {
     pub tstamp: SystemTime,
@@ -114,16 +188,31 @@ pub(crate) struct AsiImager {
     name: [u8; 20],
     cspace: ColorSpace,               // Bayer pattern
     shutter_open: Option<AtomicBool>, // Shutter open/closed not available on GenCamInfo
+    is_trigger: bool,
+    has_st4: bool,
+    max_pulse: Duration,
+    pulses: Arc<[AtomicU64; 4]>, // guide pulses started, by direction
+    sensor_size: (i32, i32),
+    bins: Vec<u64>,
+    formats: Vec<GenCamPixelBpp>,
+    usb3: bool,
     exposure: AtomicU64,
     exposure_auto: AtomicBool,
     gain: RefCell<Option<i64>>,
     roi: (GenCamRoi, GenCamPixelBpp),
+    bin: i32,
     last_exposure: RefCell<Option<LastExposureInfo>>,
     deadline: Instant,
+    // Frame buffer, sized for a full-sensor RAW16 frame. RAW8 frames are stored as
+    // packed bytes, and RAW16 frames as native-endian u16. RGB24 (3 bytes per pixel)
+    // is never selected, since `get_pixfmt` only exposes RAW8 and RAW16, and would
+    // not fit in this buffer.
     imgstor: Vec<u16>,
     sensor_ctrl: AsiSensorCtrl,
+    ctrl_caps: Vec<ASI_CONTROL_CAPS>, // all controls, including those without a property
     // Shared with GenCamInfo
     has_cooler: bool,
+    cooler_limit: Arc<CoolerLimit>,
     capturing: Arc<AtomicBool>,
     info: Arc<GenCamDescriptor>, // cloned to GenCamInfo
     device_ctrl: Arc<AsiDeviceCtrl>,
@@ -131,37 +220,96 @@ pub(crate) struct AsiImager {
     e2d: f32,
     bitdepth: u8,
     counter: u32,
+    alignment: DataAlignment,
+    last_frame: Option<(GenCamRoi, GenCamPixelBpp)>, // layout of the frame in imgstor
+    last_metadata: Option<FrameMetadata>,
+    tstamp_source: TimestampSource,
+    epoch: (Instant, SystemTime), // reference for monotonic timestamps
+    discard_first: bool,
+    format_changed: bool, // ROI or format changed since the last capture
+    downloaded: bool,     // the frame of the last exposure has been downloaded
+    #[cfg(feature = "shmem")]
+    shmem: Option<ShmemRing>, // downloaded frames are also written here
 }
 
 impl std::fmt::Debug for AsiImager {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("AsiImager")
-            .field("handle", &self.handle)
+        let mut f = f.debug_struct("AsiImager");
+        f.field("handle", &self.handle)
             .field("serial", &self.serial)
             .field("name", &self.name)
             .field("cspace", &self.cspace)
             .field("shutter_open", &self.shutter_open)
+            .field("is_trigger", &self.is_trigger)
+            .field("has_st4", &self.has_st4)
+            .field("max_pulse", &self.max_pulse)
+            .field("pulses", &self.pulses)
+            .field("sensor_size", &self.sensor_size)
+            .field("bins", &self.bins)
+            .field("formats", &self.formats)
+            .field("usb3", &self.usb3)
             .field("exposure", &self.exposure)
             .field("exposure_auto", &self.exposure_auto)
             .field("gain", &self.gain)
             .field("roi", &self.roi)
+            .field("bin", &self.bin)
             .field("last_exposure", &self.last_exposure)
             .field("deadline", &self.deadline)
             .field("imgstor", &self.imgstor)
             .field("sensor_ctrl", &self.sensor_ctrl)
+            .field("ctrl_caps", &self.ctrl_caps)
             .field("has_cooler", &self.has_cooler)
+            .field("cooler_limit", &self.cooler_limit)
             .field("capturing", &self.capturing)
             .field("info", &self.info)
             .field("device_ctrl", &self.device_ctrl)
             .field("e2d", &self.e2d)
             .field("bitdepth", &self.bitdepth)
             .field("counter", &self.counter)
-            .finish()
+            .field("alignment", &self.alignment)
+            .field("last_frame", &self.last_frame)
+            .field("last_metadata", &self.last_metadata)
+            .field("tstamp_source", &self.tstamp_source)
+            .field("epoch", &self.epoch)
+            .field("discard_first", &self.discard_first)
+            .field("format_changed", &self.format_changed)
+            .field("downloaded", &self.downloaded);
+        #[cfg(feature = "shmem")]
+        f.field("shmem", &self.shmem);
+        f.finish()
     }
 }
 
+/// Alignment of 16-bit pixel data from sensors with a lower bit depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DataAlignment {
+    /// Pixel values are shifted to fill 16 bits, as delivered by the SDK.
+    #[default]
+    LeftShifted,
+    /// Pixel values are right-aligned, i.e. range from 0 to 2^(sensor bit depth) - 1.
+    RightAligned,
+}
+
+/// Clock used to timestamp frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TimestampSource {
+    /// Wall-clock time at the start of the exposure. May jump if the system
+    /// clock is adjusted.
+    #[default]
+    SystemTime,
+    /// Monotonic clock at the start of the exposure, relative to the wall-clock
+    /// time at which the camera was opened. Intervals between frames are not
+    /// affected by system clock adjustments.
+    Monotonic,
+}
+
 /// [`GenCamInfoAsi`] implements the [`GenCamInfo`] trait for ASI cameras.
 ///
+/// The info handle exposes the device controls (cooler, temperature etc.).
+/// In addition, the exposure time ([`ExposureCtrl::ExposureTime`]) can be read,
+/// but not set, through this handle. Setting the exposure is only possible
+/// through the [`GenCam`] object, since it must not change during a capture.
+///
 /// # Examples
 /// ```
 ///
@@ -189,6 +337,8 @@ pub struct GenCamInfoAsi {
     pub(crate) expstart: Arc<AtomicOptionInstant>,
     pub(crate) info: Arc<GenCamDescriptor>,
     pub(crate) ctrl: Arc<AsiDeviceCtrl>,
+    pub(crate) props: Arc<HashMap<GenCamCtrl, Property>>, // device controls and exposure time
+    pub(crate) cooler_limit: Arc<CoolerLimit>,
 }
 
 impl std::fmt::Debug for GenCamInfoAsi {
@@ -198,6 +348,7 @@ impl std::fmt::Debug for GenCamInfoAsi {
             .field("serial", &self.serial)
             .field("name", &self.name)
             .field("has_cooler", &self.has_cooler)
+            .field("cooler_limit", &self.cooler_limit)
             .field("capturing", &self.capturing)
             .field("info", &self.info)
             .field("ctrl", &self.ctrl)
@@ -205,39 +356,362 @@ impl std::fmt::Debug for GenCamInfoAsi {
     }
 }
 
+/// Snapshot of the settable controls of an ASI camera.
+///
+/// Obtained with [`GenCamAsi::export_settings`](crate::GenCamAsi::export_settings), and
+/// applied with [`GenCamAsi::import_settings`](crate::GenCamAsi::import_settings).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraSettings {
+    /// Exposure time.
+    pub exposure: Duration,
+    /// Auto exposure enabled.
+    pub exposure_auto: bool,
+    /// Gain (dB), if the camera has a gain control.
+    pub gain: Option<f64>,
+    /// Offset (ADU), if the camera has an offset control.
+    pub offset: Option<i64>,
+    /// USB bandwidth (%), if the camera has a bandwidth control.
+    pub bandwidth: Option<i64>,
+    /// Binning factor.
+    pub bin: u32,
+    /// Region of interest, in binned pixels.
+    pub roi: GenCamRoi,
+    /// Pixel format.
+    pub pixel_format: GenCamPixelBpp,
+    /// Horizontal and vertical flip.
+    pub flip: (bool, bool),
+    /// Cooler target temperature (C), if the camera has a cooler.
+    pub cooler_target: Option<i64>,
+}
+
+/// A named set of control values, e.g. for a filter or an observing mode.
+///
+/// Unlike [`CameraSettings`], a preset only holds the controls it was given,
+/// and leaves every other control untouched when applied with
+/// [`GenCamAsi::apply_preset`](crate::GenCamAsi::apply_preset).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    name: String,
+    controls: Vec<(GenCamCtrl, PropertyValue, bool)>,
+}
+
+impl Preset {
+    /// Create an empty preset.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            controls: Vec::new(),
+        }
+    }
+
+    /// Set `control` to `value` (and auto mode `auto`) when the preset is applied.
+    ///
+    /// Controls are applied in the order they were first added; setting a control
+    /// again replaces its value.
+    pub fn with(mut self, control: GenCamCtrl, value: PropertyValue, auto: bool) -> Self {
+        self.set(control, value, auto);
+        self
+    }
+
+    /// Set `control` to `value` (and auto mode `auto`) when the preset is applied.
+    pub fn set(&mut self, control: GenCamCtrl, value: PropertyValue, auto: bool) {
+        if let Some(entry) = self
+            .controls
+            .iter_mut()
+            .find(|(ctrl, _, _)| *ctrl == control)
+        {
+            *entry = (control, value, auto);
+        } else {
+            self.controls.push((control, value, auto));
+        }
+    }
+
+    /// Get the name of the preset.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the controls of the preset, in the order they are applied.
+    pub fn controls(&self) -> &[(GenCamCtrl, PropertyValue, bool)] {
+        &self.controls
+    }
+}
+
+/// Current gain of an ASI camera, in the representations used by settings UIs.
+///
+/// Obtained with [`GenCamAsi::gain_info`](crate::GenCamAsi::gain_info).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainInfo {
+    /// Raw gain value reported by the SDK (0.1 dB units).
+    pub raw: i64,
+    /// Gain in dB.
+    pub db: f64,
+    /// Gain as a percentage of the camera's gain range.
+    pub percent: f32,
+    /// Auto gain enabled.
+    pub auto: bool,
+}
+
+/// Mean pixel values in the corners and the center of a frame.
+///
+/// Obtained with [`GenCamAsi::corner_statistics`](crate::GenCamAsi::corner_statistics).
+/// Corners that are markedly brighter than the center, especially in long
+/// dark frames, indicate amplifier glow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CornerStats {
+    /// Mean of the top-left corner.
+    pub top_left: f64,
+    /// Mean of the top-right corner.
+    pub top_right: f64,
+    /// Mean of the bottom-left corner.
+    pub bottom_left: f64,
+    /// Mean of the bottom-right corner.
+    pub bottom_right: f64,
+    /// Mean of the center.
+    pub center: f64,
+}
+
+/// Compute the mean of the corners and the center of a `width` x `height` frame,
+/// over regions of `rw` x `rh` pixels.
+///
+/// The regions are clamped to half the frame in each dimension, so that the
+/// corners do not overlap. Returns `None` if the frame is empty, or `data` is
+/// shorter than the frame.
+fn corner_stats<T: Copy + Into<f64>>(
+    data: &[T],
+    width: usize,
+    height: usize,
+    (rw, rh): (usize, usize),
+) -> Option<CornerStats> {
+    if width == 0 || height == 0 || data.len() < width * height {
+        return None;
+    }
+    let rw = rw.min(width / 2).max(1);
+    let rh = rh.min(height / 2).max(1);
+    let mean = |x0: usize, y0: usize| {
+        let sum: f64 = (y0..y0 + rh)
+            .flat_map(|y| &data[y * width + x0..y * width + x0 + rw])
+            .map(|&px| Into::<f64>::into(px))
+            .sum();
+        sum / (rw * rh) as f64
+    };
+    Some(CornerStats {
+        top_left: mean(0, 0),
+        top_right: mean(width - rw, 0),
+        bottom_left: mean(0, height - rh),
+        bottom_right: mean(width - rw, height - rh),
+        center: mean((width - rw) / 2, (height - rh) / 2),
+    })
+}
+
+/// Pixel statistics of a frame.
+///
+/// Obtained with [`GenCamAsi::frame_stats`](crate::GenCamAsi::frame_stats).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Minimum pixel value.
+    pub min: u16,
+    /// Maximum pixel value.
+    pub max: u16,
+    /// Mean pixel value.
+    pub mean: f64,
+    /// Standard deviation of the pixel values.
+    pub stddev: f64,
+    /// Fraction of the pixels at the saturation level, from 0 to 1.
+    pub saturated: f64,
+}
+
+/// Metadata of a downloaded frame.
+///
+/// Obtained with [`GenCamAsi::last_frame_metadata`](crate::GenCamAsi::last_frame_metadata).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameMetadata {
+    /// Serial number of the frame since the camera was opened.
+    pub serial: u32,
+    /// Time at which the exposure started.
+    pub timestamp: SystemTime,
+    /// Exposure time.
+    pub exposure: Duration,
+    /// Whether the frame was taken with the shutter closed.
+    pub darkframe: bool,
+    /// Gain (dB), if the camera has a gain control.
+    pub gain: Option<f64>,
+    /// Electrons per ADU at the time of the exposure.
+    pub electrons_per_adu: f32,
+    /// Sensor temperature (C) at readout, if available.
+    pub temperature: Option<f32>,
+    /// Region of interest.
+    pub roi: GenCamRoi,
+    /// Pixel format.
+    pub pixel_format: GenCamPixelBpp,
+    /// Binning factor.
+    pub bin: i32,
+    /// Horizontal and vertical flip, if the camera has a flip control.
+    pub flip: Option<(bool, bool)>,
+}
+
+/// Get the exposure that brings the mean of a flat frame from `fraction` to `target`
+/// (fractions of full scale), assuming the mean scales linearly with the exposure.
+fn next_flat_exposure(
+    exposure: Duration,
+    fraction: f64,
+    target: f64,
+    min: Duration,
+    max: Duration,
+) -> Duration {
+    let scale = if fraction > 0.0 {
+        (target / fraction).min(FLAT_MAX_STEP)
+    } else {
+        FLAT_MAX_STEP
+    };
+    exposure.mul_f64(scale).clamp(min, max)
+}
+
+/// Convert a percentage (0-100) of the range `min..=max` to a raw control value,
+/// rounded to the nearest integer.
+///
+/// Percentages outside 0-100 are clamped, and an empty range maps to `min`.
+fn percent_to_raw(percent: f32, min: i64, max: i64) -> i64 {
+    if max <= min {
+        return min;
+    }
+    let percent = percent.clamp(0.0, 100.0) as f64;
+    min + ((max - min) as f64 * percent / 100.0).round() as i64
+}
+
+/// Convert a raw control value to a percentage (0-100) of the range `min..=max`.
+///
+/// Values outside the range are clamped, and an empty range maps to 0%.
+fn raw_to_percent(raw: i64, min: i64, max: i64) -> f32 {
+    if max <= min {
+        return 0.0;
+    }
+    let raw = raw.clamp(min, max);
+    ((raw - min) as f64 / (max - min) as f64 * 100.0) as f32
+}
+
+/// Compute the statistics of the pixels in `data`, or `None` if it is empty.
+fn frame_stats<T: Copy + Into<u16>>(data: &[T], saturation: u16) -> Option<FrameStats> {
+    if data.is_empty() {
+        return None;
+    }
+    let (mut min, mut max) = (u16::MAX, u16::MIN);
+    let (mut sum, mut sumsq) = (0.0, 0.0);
+    let mut saturated = 0usize;
+    for &px in data {
+        let px: u16 = px.into();
+        min = min.min(px);
+        max = max.max(px);
+        if px >= saturation {
+            saturated += 1;
+        }
+        let px = px as f64;
+        sum += px;
+        sumsq += px * px;
+    }
+    let n = data.len() as f64;
+    let mean = sum / n;
+    Some(FrameStats {
+        min,
+        max,
+        mean,
+        stddev: (sumsq / n - mean * mean).max(0.0).sqrt(),
+        saturated: saturated as f64 / n,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct CaptureInfo {
     pub roi: AsiRoi,
     pub last_exposure: Option<LastExposureInfo>,
 }
 
-pub fn open_device(ginfo: &GenCamDescriptor) -> Result<AsiImager, GenCamError> {
+/// Open the camera, read its controls, and close it again.
+pub(crate) fn describe_device(
+    ginfo: &GenCamDescriptor,
+) -> Result<HashMap<GenCamCtrl, Property>, GenCamError> {
     let handle = ginfo.id as _;
-    ASICALL!(ASIInitCamera(handle)).map_err(|e| match e {
+    ASICALL!(ASIOpenCamera(handle)).map_err(|e| match e {
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::CameraRemoved(_, _) => GenCamError::CameraRemoved,
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
+    let res = get_info(handle).and_then(|info| {
+        let caps = get_control_caps(handle)?;
+        let (sensor_ctrl, device_ctrl) = get_split_ctrl(&info, &caps);
+        let mut out = sensor_ctrl.list_properties().clone();
+        out.extend(device_ctrl.list_properties().clone());
+        Ok(out)
+    });
+    if let Err(e) = ASICALL!(ASICloseCamera(handle)) {
+        warn!("Failed to close camera: {:?}", e);
+    }
+    res
+}
+
+/// Open the camera described by `ginfo`.
+///
+/// If `cached` holds the handle of the already initialized camera, the camera
+/// is not initialized again.
+pub fn open_device(
+    ginfo: &GenCamDescriptor,
+    cached: Option<Arc<AsiHandle>>,
+) -> Result<AsiImager, GenCamError> {
+    let handle = ginfo.id as _;
+    let ahandle = match cached {
+        Some(ahandle) => ahandle,
+        None => {
+            // the camera is not opened when listing passively; opening it again is a no-op
+            ASICALL!(ASIOpenCamera(handle)).map_err(|e| match e {
+                AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+                AsiError::CameraRemoved(_, _) => GenCamError::CameraRemoved,
+                _ => GenCamError::GeneralError(e.to_string()),
+            })?;
+            ASICALL!(ASIInitCamera(handle)).map_err(|e| match e {
+                AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+                AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+                AsiError::CameraRemoved(_, _) => GenCamError::CameraRemoved,
+                _ => GenCamError::GeneralError(e.to_string()),
+            })?;
+            Arc::new(handle.into())
+        }
+    };
     let info = get_info(handle)?;
+    if UsbSpeed::is_degraded(&info) {
+        warn!(
+            "{} is a USB3 camera, but is connected at USB2 speed",
+            string_from_char(&info.Name)
+        );
+    }
     let caps = get_control_caps(handle)?;
     let (sensor_ctrl, device_ctrl) = get_split_ctrl(&info, &caps);
     let mut roi = AsiRoi::get(handle).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
-    roi.fmt = ASI_IMG_TYPE_ASI_IMG_RAW8;
+    // mono cameras default to 16-bit for the extra dynamic range, as the legacy driver did
+    let mono = info.IsColorCam != ASI_BOOL_ASI_TRUE as _;
+    let formats = get_pixfmt(&info.SupportedVideoFormat, ASI_IMG_TYPE_ASI_IMG_END as _);
+    let raw16 = formats.contains(&GenCamPixelBpp::Bpp16);
+    roi.fmt = if mono && raw16 {
+        ASI_IMG_TYPE_ASI_IMG_RAW16
+    } else {
+        ASI_IMG_TYPE_ASI_IMG_RAW8
+    };
     roi.set(handle).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
     let roi = AsiRoi::get(handle).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
+    let bin = roi.bin;
     let bpp = match roi.fmt {
         ASI_IMG_TYPE_ASI_IMG_RAW8 => GenCamPixelBpp::Bpp8,
         ASI_IMG_TYPE_ASI_IMG_RAW16 => GenCamPixelBpp::Bpp16,
@@ -257,7 +731,7 @@ pub fn open_device(ginfo: &GenCamDescriptor) -> Result<AsiImager, GenCamError> {
     let sn = get_sn(handle).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
     let sname = string_from_char(&info.Name);
     let sname_ref = sname.as_bytes();
@@ -275,25 +749,45 @@ pub fn open_device(ginfo: &GenCamDescriptor) -> Result<AsiImager, GenCamError> {
     } else {
         ColorSpace::Gray
     };
+    let npix = (info.MaxWidth as usize)
+        .checked_mul(info.MaxHeight as usize)
+        .ok_or_else(|| {
+            GenCamError::GeneralError(format!(
+                "ASI: Sensor size {}x{} is too large",
+                info.MaxWidth, info.MaxHeight
+            ))
+        })?;
     let out = AsiImager {
-        handle: Arc::new(handle.into()),
+        handle: ahandle,
         serial: sn,
         name,
         cspace: bayer,
         has_cooler: info.IsCoolerCam == ASI_BOOL_ASI_TRUE as _,
+        cooler_limit: Default::default(),
         shutter_open: if info.MechanicalShutter == ASI_BOOL_ASI_TRUE as _ {
             Some(AtomicBool::new(false))
         } else {
             None
         },
+        is_trigger: info.IsTriggerCam == ASI_BOOL_ASI_TRUE as _,
+        has_st4: info.ST4Port == ASI_BOOL_ASI_TRUE as _,
+        max_pulse: MAX_PULSE_DURATION,
+        pulses: Default::default(),
+        sensor_size: (info.MaxWidth as _, info.MaxHeight as _),
+        bins: get_bins(&info.SupportedBins, 0),
+        formats,
+        usb3: info.IsUSB3Camera == ASI_BOOL_ASI_TRUE as _
+            && info.IsUSB3Host == ASI_BOOL_ASI_TRUE as _,
         capturing: Arc::new(AtomicBool::new(false)),
         exposure: AtomicU64::new(0),
         exposure_auto: AtomicBool::new(false),
         gain: RefCell::new(None),
         roi: (roi, bpp),
+        bin,
         last_exposure: RefCell::new(None),
-        imgstor: vec![0u16; (info.MaxHeight * info.MaxWidth) as _],
+        imgstor: vec![0u16; npix],
         sensor_ctrl,
+        ctrl_caps: caps,
         info: Arc::new(ginfo.clone()),
         device_ctrl: Arc::new(device_ctrl),
         expstart: Arc::new(AtomicOptionInstant::new(None)),
@@ -301,6 +795,16 @@ pub fn open_device(ginfo: &GenCamDescriptor) -> Result<AsiImager, GenCamError> {
         e2d: info.ElecPerADU as _,
         bitdepth: info.BitDepth as _,
         counter: 0,
+        alignment: DataAlignment::default(),
+        last_frame: None,
+        last_metadata: None,
+        tstamp_source: TimestampSource::default(),
+        epoch: (Instant::now(), SystemTime::now()),
+        discard_first: true,
+        format_changed: true,
+        downloaded: false,
+        #[cfg(feature = "shmem")]
+        shmem: None,
     };
     out.get_exposure()?;
     Ok(out)
@@ -331,6 +835,119 @@ impl AsiImager {
         Ok(())
     }
 
+    /// Set exposure without reading it back from the device.
+    ///
+    /// The internal state is updated from the requested values, trusting that
+    /// the camera applied them as-is.
+    pub(crate) fn set_exposure_fast(
+        &self,
+        exposure: Duration,
+        auto: bool,
+    ) -> Result<(), GenCamError> {
+        if self.capturing.load(Ordering::SeqCst) {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        // the value is not read back, so it must be one the camera accepts as-is
+        self.check_exposure(exposure)?;
+        let handle = self.handle.handle();
+        let value = exposure.as_micros() as _;
+        let auto_raw = to_asibool(auto) as _;
+        set_control_value(handle, AsiControlType::Exposure, value, auto_raw)?;
+        self.exposure.store(value as _, Ordering::SeqCst);
+        self.exposure_auto.store(auto, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Check that `exposure` is within the exposure range of the camera.
+    fn check_exposure(&self, exposure: Duration) -> Result<(), GenCamError> {
+        let (min, max) = self.exposure_range()?;
+        if exposure < min || exposure > max {
+            return Err(GenCamError::PropertyError {
+                control: ExposureCtrl::ExposureTime.into(),
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        Ok(())
+    }
+
+    /// Get the (min, max) exposure supported by the camera.
+    pub fn exposure_range(&self) -> Result<(Duration, Duration), GenCamError> {
+        let control: GenCamCtrl = ExposureCtrl::ExposureTime.into();
+        let perr = |error: PropertyError| GenCamError::PropertyError { control, error };
+        let (_, lims) = self
+            .sensor_ctrl
+            .get_controller(&control)
+            .ok_or(perr(PropertyError::NotFound))?;
+        let min: Duration = lims.get_min().map_err(perr)?.try_into().map_err(perr)?;
+        let max: Duration = lims.get_max().map_err(perr)?.try_into().map_err(perr)?;
+        Ok((min, max))
+    }
+
+    /// Replace the maximum exposure reported by the camera, and clamp the current
+    /// exposure to it.
+    pub fn set_max_exposure_override(&mut self, max: Duration) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let control: GenCamCtrl = ExposureCtrl::ExposureTime.into();
+        let perr = |error: PropertyError| GenCamError::PropertyError { control, error };
+        let lims = self
+            .sensor_ctrl
+            .dcaps
+            .get(&control)
+            .ok_or(perr(PropertyError::NotFound))?;
+        let min: Duration = lims.get_min().map_err(perr)?.try_into().map_err(perr)?;
+        let default: Duration = lims.get_default().map_err(perr)?.try_into().map_err(perr)?;
+        if max < min {
+            return Err(perr(PropertyError::ValueNotSupported));
+        }
+        let lims = Property::new(
+            PropertyLims::Duration {
+                min,
+                max,
+                step: Duration::from_micros(1),
+                default: default.min(max),
+            },
+            lims.auto_supported(),
+            lims.is_readonly(),
+        );
+        self.sensor_ctrl.dcaps.insert(control, lims);
+        let (exposure, auto) = self.get_exposure()?;
+        if exposure > max {
+            self.set_exposure(max, auto)?;
+        }
+        Ok(())
+    }
+
+    /// Clamp the exposure to the limits of the camera, and set it as a manual exposure.
+    ///
+    /// Returns the exposure that was applied.
+    pub(crate) fn set_exposure_clamped(&self, exposure: Duration) -> Result<Duration, GenCamError> {
+        let (min, max) = self.exposure_range()?;
+        self.set_exposure(exposure.clamp(min, max), false)?;
+        // `set_exposure` reads the applied value back from the camera
+        let applied = self.exposure.load(Ordering::SeqCst);
+        Ok(Duration::from_micros(applied))
+    }
+
+    /// Get the last known exposure (us), without querying the device.
+    pub(crate) fn exposure_micros(&self) -> u64 {
+        self.exposure.load(Ordering::SeqCst)
+    }
+
+    /// Set a manual exposure (us) to device and update internal state
+    pub(crate) fn set_exposure_micros(&self, exposure: u64) -> Result<(), GenCamError> {
+        if self.capturing.load(Ordering::SeqCst) {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        self.check_exposure(Duration::from_micros(exposure))?;
+        let handle = self.handle.handle();
+        let auto = ASI_BOOL_ASI_FALSE as _;
+        set_control_value(handle, AsiControlType::Exposure, exposure as _, auto)?;
+        self.get_exposure()?;
+        Ok(())
+    }
+
     /// Get exposure from device and update internal state
     pub(crate) fn get_exposure(&self) -> Result<(Duration, bool), GenCamError> {
         let handle = self.handle.handle();
@@ -344,28 +961,51 @@ impl AsiImager {
         ))
     }
 
+    /// Check whether auto-exposure is enabled on the device, and update internal state
+    pub(crate) fn is_auto_exposure(&self) -> Result<bool, GenCamError> {
+        let (_, auto) = self.get_exposure()?;
+        Ok(auto)
+    }
+
     pub(crate) fn set_roi_raw(&mut self, roi: &AsiRoi) -> Result<(), GenCamError> {
         let handle = self.handle.handle();
         roi.set(handle).map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            AsiError::InvalidControlType(src, args) => {
-                GenCamError::InvalidControlType(format!("src: {src:?}, args: {args:?}"))
-            }
-            AsiError::InvalidImage(src, args) => {
-                GenCamError::InvalidImageType(format!("src: {src:?}, args: {args:?}"))
-            }
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            AsiError::InvalidControlType(_, _) => GenCamError::InvalidControlType(e.to_string()),
+            AsiError::InvalidImage(_, _) => GenCamError::InvalidImageType(e.to_string()),
+            _ => GenCamError::GeneralError(e.to_string()),
         })?;
+        // the SDK may adjust the ROI, keep what was actually applied
         let roi = AsiRoi::get(handle).map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            _ => GenCamError::GeneralError(e.to_string()),
         })?;
         self.roi = roi.convert();
+        self.bin = roi.bin;
+        self.format_changed = true;
         Ok(())
     }
 
+    pub fn set_discard_first_after_change(&mut self, discard: bool) {
+        self.discard_first = discard;
+    }
+
+    pub fn discard_first_after_change(&self) -> bool {
+        self.discard_first
+    }
+
+    pub fn restore_full_roi_on_drop(&mut self, restore: bool) {
+        self.handle.set_restore_roi(restore);
+    }
+
+    /// Check if the next frame should be discarded, because the ROI or format
+    /// changed since the last download. The flag is cleared by a successful download.
+    pub(crate) fn needs_discard(&self) -> bool {
+        self.format_changed && self.discard_first
+    }
+
     pub(crate) fn get_gain(&self) -> Result<i64, GenCamError> {
         let handle = self.handle.handle();
         if let Ok(mut gainref) = self.gain.try_borrow_mut() {
@@ -377,7 +1017,7 @@ impl AsiImager {
                 Ok(gain)
             }
         } else {
-            Err(GenCamError::AccessViolation)
+            Err(access_violation("gain"))
         }
     }
 
@@ -390,7 +1030,7 @@ impl AsiImager {
             *gainref = Some(gain);
             Ok(())
         } else {
-            Err(GenCamError::AccessViolation)
+            Err(access_violation("gain"))
         }
     }
 
@@ -427,10 +1067,28 @@ impl AsiImager {
         }
     }
 
+    /// Check that a frame with the current ROI and pixel format fits in the image buffer.
+    fn check_buffer(&self) -> Result<(), GenCamError> {
+        let (roi, bpp) = &self.roi;
+        let len = self.imgstor.len() * size_of::<u16>();
+        let bytes_per_px = if *bpp == GenCamPixelBpp::Bpp16 { 2 } else { 1 };
+        let size = (roi.width as usize)
+            .checked_mul(roi.height as usize)
+            .and_then(|n| n.checked_mul(bytes_per_px));
+        match size {
+            Some(size) if size <= len => Ok(()),
+            _ => Err(GenCamError::InvalidFormat(format!(
+                "ROI {}x{} ({:?}) does not fit in the {} byte image buffer",
+                roi.width, roi.height, bpp, len
+            ))),
+        }
+    }
+
     pub fn start_exposure(&mut self) -> Result<(), GenCamError> {
         if self.capturing.load(Ordering::SeqCst) {
             return Err(GenCamError::ExposureInProgress);
         }
+        self.check_buffer()?;
         let handle = self.handle.handle();
         self.capturing.store(true, Ordering::SeqCst); // indicate we are capturing
                                                       // now we are capturing
@@ -441,7 +1099,10 @@ impl AsiImager {
         };
 
         let mut last_exposure = LastExposureInfo {
-            tstamp: SystemTime::now(),
+            tstamp: match self.tstamp_source {
+                TimestampSource::SystemTime => SystemTime::now(),
+                TimestampSource::Monotonic => self.epoch.1 + self.epoch.0.elapsed(),
+            },
             exposure: Duration::from_micros(self.exposure.load(Ordering::SeqCst)),
             darkframe,
             gain: self.get_gain().ok(),
@@ -458,7 +1119,11 @@ impl AsiImager {
             match e {
                 AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
                 AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-                _ => GenCamError::GeneralError(format!("{:?}", e)),
+                AsiError::VideoModeActive(_, _) => GenCamError::GeneralError(with_code(
+                    "Video capture is active, stop it before starting an exposure",
+                    &e,
+                )),
+                _ => GenCamError::GeneralError(e.to_string()),
             }
         })?;
         let state = self.handle.state_raw()?;
@@ -475,9 +1140,10 @@ impl AsiImager {
             AsiExposureStatus::Working | AsiExposureStatus::Success => {}
         }
         let Ok(mut lexp) = self.last_exposure.try_borrow_mut() else {
-            return Err(GenCamError::AccessViolation);
+            return Err(access_violation("last_exposure"));
         };
         *lexp = Some(last_exposure);
+        self.downloaded = false;
         Ok(())
     }
 
@@ -489,59 +1155,124 @@ impl AsiImager {
         let res = ASICALL!(ASIStopExposure(handle)).map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            _ => GenCamError::GeneralError(e.to_string()),
         });
         self.capturing.store(false, Ordering::SeqCst);
         res
     }
 
+    /// Stop the exposure in progress, and discard a completed frame that has not
+    /// been downloaded, so that the next capture starts clean.
+    pub fn abort_and_drain(&mut self) -> Result<(), GenCamError> {
+        let handle = self.handle.handle();
+        ASICALL!(ASIStopExposure(handle)).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })?;
+        if matches!(self.handle.state_raw()?, AsiExposureStatus::Success) {
+            let ptr = self.imgstor.as_mut_ptr();
+            let len = self.imgstor.len() * size_of::<u16>();
+            if let Err(e) = ASICALL!(ASIGetDataAfterExp(handle, ptr as _, len as _)) {
+                warn!("Could not drain completed frame: {}", e);
+            }
+        }
+        *self
+            .last_exposure
+            .try_borrow_mut()
+            .map_err(|_| access_violation("last_exposure"))? = None;
+        self.downloaded = false;
+        self.reset_capture();
+        Ok(())
+    }
+
+    /// Mark the imager idle, after an exposure has been downloaded or has failed.
+    fn reset_capture(&self) {
+        self.capturing.store(false, Ordering::SeqCst);
+        self.expstart.store(None, Ordering::SeqCst);
+    }
+
     pub fn download_image(&mut self) -> Result<GenericImageRef, GenCamError> {
         // check if capturing, if not return error
         if !self.capturing.load(Ordering::SeqCst) {
+            if self.downloaded {
+                return Err(GenCamError::GeneralError("Frame already downloaded".into()));
+            }
             return Err(GenCamError::ExposureNotStarted);
         }
         // capturing, check state
         let handle = self.handle.handle();
         let state = self.handle.state_raw()?;
-        let temp = self.get_temperature().unwrap_or(-273.16);
+        let temp = self.get_temperature().ok();
         let (roi, bpp) = &self.roi;
         let mut expinfo = self
             .last_exposure
             .try_borrow_mut()
-            .map_err(|_| GenCamError::AccessViolation)?;
+            .map_err(|_| access_violation("last_exposure"))?;
         let expinfo = match state {
             AsiExposureStatus::Working => Err(GenCamError::ExposureInProgress),
             AsiExposureStatus::Failed => {
-                self.capturing.store(false, Ordering::SeqCst);
+                *expinfo = None;
+                self.reset_capture();
                 Err(GenCamError::ExposureFailed("".into()))
             }
             AsiExposureStatus::Idle => {
-                self.capturing.store(false, Ordering::SeqCst);
                 *expinfo = None;
-                self.expstart.store(None, Ordering::SeqCst);
+                self.reset_capture();
                 Err(GenCamError::ExposureNotStarted)
             }
             AsiExposureStatus::Success => {
                 let now = SystemTime::now();
                 let Some(expinfo) = expinfo.take() else {
+                    self.reset_capture();
                     return Err(GenCamError::ExposureNotStarted);
                 };
+                if let Err(e) = self.check_buffer() {
+                    self.reset_capture();
+                    return Err(e);
+                }
                 let mut ptr = self.imgstor.as_mut_ptr();
                 let len = self.imgstor.len() * size_of::<u16>();
-                ASICALL!(ASIGetDataAfterExp(handle, ptr as _, len as _)).map_err(|e| {
-                    self.capturing.store(false, Ordering::SeqCst);
-                    match e {
-                        AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
-                        AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-                        AsiError::Timeout(_, _) => GenCamError::TimedOut,
-                        _ => GenCamError::GeneralError(format!("{:?}", e)),
-                    }
+                let res = ASICALL_RETRY!(
+                    ASIGetDataAfterExp(handle, ptr as _, len as _),
+                    DOWNLOAD_RETRY_ATTEMPTS,
+                    DOWNLOAD_RETRY_DELAY
+                );
+                // the exposure is over, whether or not the download succeeded
+                self.reset_capture();
+                res.map_err(|e| match e {
+                    AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+                    AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+                    AsiError::Timeout(_, _) => GenCamError::TimedOut,
+                    _ => GenCamError::GeneralError(e.to_string()),
                 })?;
-                self.capturing.store(false, Ordering::SeqCst); // image has been downloaded
                 Ok(expinfo)
             }
         }?;
 
+        if *bpp == GenCamPixelBpp::Bpp16 && self.alignment == DataAlignment::RightAligned {
+            let shift = 16u8.saturating_sub(self.bitdepth);
+            let npix = roi.width as usize * roi.height as usize;
+            self.imgstor[..npix].iter_mut().for_each(|px| *px >>= shift);
+        }
+        self.downloaded = true;
+        self.last_frame = Some((*roi, *bpp));
+        self.last_metadata = Some(FrameMetadata {
+            serial: self.counter,
+            timestamp: expinfo.tstamp,
+            exposure: expinfo.exposure,
+            darkframe: expinfo.darkframe,
+            gain: expinfo.gain.map(|v| v as f64 * 0.1),
+            electrons_per_adu: expinfo.e2d,
+            temperature: temp,
+            roi: *roi,
+            pixel_format: *bpp,
+            bin: self.bin,
+            flip: expinfo.flip,
+        });
+        self.format_changed = false;
+        #[cfg(feature = "shmem")]
+        self.write_shmem();
         let width = roi.width as _;
         let height = roi.height as _;
         let ptr = &mut self.imgstor;
@@ -612,9 +1343,13 @@ impl AsiImager {
         img.insert_key("SENSORBPP", (self.bitdepth, "Sensor bit depth"));
         img.insert_key("XOFFSET", (roi.x_min, "X offset"));
         img.insert_key("YOFFSET", (roi.y_min, "Y offset"));
-        img.insert_key("XBINNING", (1, "X binning"));
-        img.insert_key("YBINNING", (1, "Y binning"));
-        img.insert_key("CCD-TEMP", (temp, "CCD temperature (C)"));
+        img.insert_key("XBINNING", (self.bin, "X binning"));
+        img.insert_key("YBINNING", (self.bin, "Y binning"));
+        if let Some((flip_x, flip_y)) = expinfo.flip {
+            img.insert_key("FLIPX", (flip_x, "Image flipped horizontally"));
+            img.insert_key("FLIPY", (flip_y, "Image flipped vertically"));
+        }
+        img.insert_key("CCD-TEMP", (temp.unwrap_or(-273.16), "CCD temperature (C)"));
         img.insert_key(
             "CAMERA",
             (
@@ -640,64 +1375,355 @@ impl AsiImager {
         Ok(img)
     }
 
-    pub fn get_property(&self, prop: &GenCamCtrl) -> Result<(PropertyValue, bool), GenCamError> {
-        if !self.sensor_ctrl.contains(prop) & !self.device_ctrl.contains(prop) {
-            return Err(GenCamError::PropertyError {
-                control: *prop,
-                error: PropertyError::NotFound,
-            });
-        };
-        match prop {
-            GenCamCtrl::Exposure(ExposureCtrl::ExposureTime) => {
-                let (exp, auto) = self.get_exposure()?;
-                Ok((PropertyValue::from(exp), auto))
+    /// Download the image, reporting progress through `cb`.
+    ///
+    /// `ASIGetDataAfterExp` reads the whole frame in one blocking call and does not
+    /// report readout progress, so `cb` is invoked with `0.0` before the readout
+    /// starts and with `1.0` once the frame has been downloaded. The callback is not
+    /// invoked with `1.0` if the download fails.
+    pub fn download_image_with_progress(
+        &mut self,
+        cb: impl Fn(f32),
+    ) -> Result<GenericImageRef, GenCamError> {
+        cb(0.0);
+        let img = self.download_image()?;
+        cb(1.0);
+        Ok(img)
+    }
+
+    /// Download the image and reduce it to a single luminance channel.
+    ///
+    /// Bayer frames are debayered (nearest neighbor) before the luma conversion,
+    /// mono frames are returned unchanged.
+    pub fn download_luma(&mut self) -> Result<GenericImage, GenCamError> {
+        let img = self.download_image()?;
+        if !img.color_space().is_bayer() {
+            return Ok(img.into());
+        }
+        // debayer straight from the frame buffer, without an intermediate copy
+        let mut img = img
+            .debayer(DemosaicMethod::Nearest)
+            .map_err(|e| GenCamError::InvalidFormat(format!("{:?}", e)))?;
+        img.to_luma()
+            .map_err(|e| GenCamError::InvalidFormat(format!("{:?}", e)))?;
+        Ok(img)
+    }
+
+    /// Download the image, with the pixel values scaled to [0, 1] by the saturation
+    /// level of the sensor.
+    pub fn download_image_f32(&mut self) -> Result<GenericImage, GenCamError> {
+        let mut img: GenericImage = self.download_image()?.into();
+        let data: Vec<f32> = match img.get_image() {
+            DynamicImageOwned::U8(img) => {
+                let max = u8::MAX as f32;
+                img.as_slice().iter().map(|&px| px as f32 / max).collect()
             }
-            GenCamCtrl::Sensor(SensorCtrl::PixelFormat) => {
-                let val: GenCamPixelBpp = (self.roi.1);
-                Ok((PropertyValue::PixelFmt(val), false))
+            DynamicImageOwned::U16(img) => {
+                let max = self.saturation_level() as f32;
+                img.as_slice().iter().map(|&px| px as f32 / max).collect()
             }
-            GenCamCtrl::Sensor(SensorCtrl::ReverseX) => {
-                let (flipx, _) = self.get_flip()?;
-                Ok((PropertyValue::Bool(flipx), false))
+            _ => {
+                return Err(GenCamError::InvalidFormat(format!(
+                    "Unexpected pixel type {:?}",
+                    img.pixel_type()
+                )))
             }
-            GenCamCtrl::Sensor(SensorCtrl::ReverseY) => {
-                let (_, flipy) = self.get_flip()?;
-                Ok((PropertyValue::Bool(flipy), false))
+        };
+        let scaled = ImageOwned::new(data, img.width(), img.height(), img.color_space())
+            .map_err(|e| GenCamError::InvalidFormat(format!("{:?}", e)))?;
+        *img.get_image_mut() = DynamicImageOwned::F32(scaled);
+        Ok(img)
+    }
+
+    /// Download the image into an [`image`] buffer, [`Luma<u8>`](image::Luma) for
+    /// RAW8 and [`Luma<u16>`](image::Luma) for RAW16 frames.
+    #[cfg(feature = "image")]
+    pub fn download_into_image_buffer(&mut self) -> Result<image::DynamicImage, GenCamError> {
+        use image::{DynamicImage, ImageBuffer, Luma};
+        self.download_image()?;
+        let Some((roi, bpp)) = self.last_frame else {
+            return Err(GenCamError::ExposureNotStarted);
+        };
+        let (width, height) = (roi.width as u32, roi.height as u32);
+        let npix = roi.width as usize * roi.height as usize;
+        let img = match bpp {
+            GenCamPixelBpp::Bpp8 => {
+                let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+                ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data[..npix].to_vec())
+                    .map(DynamicImage::ImageLuma8)
             }
-            GenCamCtrl::Sensor(SensorCtrl::ShutterMode) => {
-                if let Some(open) = &self.shutter_open {
-                    Ok((PropertyValue::Bool(open.load(Ordering::SeqCst)), false))
-                } else {
-                    Err(GenCamError::PropertyError {
-                        control: *prop,
-                        error: PropertyError::NotFound,
-                    })
-                }
+            _ => {
+                ImageBuffer::<Luma<u16>, _>::from_raw(width, height, self.imgstor[..npix].to_vec())
+                    .map(DynamicImage::ImageLuma16)
             }
-            GenCamCtrl::Analog(AnalogCtrl::Gain) => {
-                let val = self.get_gain()?;
-                Ok((PropertyValue::from(val as f64 * 0.1), false))
+        };
+        img.ok_or_else(|| {
+            GenCamError::InvalidFormat(format!(
+                "{}x{} frame does not fit the image buffer",
+                width, height
+            ))
+        })
+    }
+
+    /// Capture `n` frames with the current settings, and return their average.
+    ///
+    /// The sum is accumulated in 64 bits, and the mean (in ADU, not rounded) is
+    /// returned as 32-bit floating point pixels, with the metadata of the first frame.
+    /// Cancelling the exposure aborts the capture with [`GenCamError::ExposureNotStarted`].
+    pub fn capture_averaged(&mut self, n: usize) -> Result<GenericImage, GenCamError> {
+        if n == 0 {
+            return Err(GenCamError::GeneralError(
+                "Number of frames to average must be non-zero".into(),
+            ));
+        }
+        let mut sum: Vec<u64> = Vec::new();
+        let mut first: Option<GenericImage> = None;
+        for _ in 0..n {
+            let img = self.capture_one()?;
+            let Some((roi, bpp)) = self.last_frame else {
+                return Err(GenCamError::ExposureNotStarted);
+            };
+            let npix = roi.width as usize * roi.height as usize;
+            sum.resize(npix, 0);
+            if bpp == GenCamPixelBpp::Bpp8 {
+                let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+                sum.iter_mut()
+                    .zip(&data[..npix])
+                    .for_each(|(s, px)| *s += *px as u64);
+            } else {
+                sum.iter_mut()
+                    .zip(&self.imgstor[..npix])
+                    .for_each(|(s, px)| *s += *px as u64);
             }
-            _ => self.device_ctrl.get_value(&self.handle, prop),
+            first.get_or_insert(img);
         }
+        let mut out = first.expect("at least one frame is captured");
+        let n = n as f64;
+        let mean: Vec<f32> = sum.iter().map(|&s| (s as f64 / n) as f32).collect();
+        let mean = ImageOwned::new(mean, out.width(), out.height(), out.color_space())
+            .map_err(|e| GenCamError::InvalidFormat(format!("{:?}", e)))?;
+        *out.get_image_mut() = DynamicImageOwned::F32(mean);
+        Ok(out)
     }
 
-    pub fn set_property(
+    /// Start an exposure with the current settings, and wait until the image is
+    /// ready for download.
+    pub(crate) fn expose(&mut self) -> Result<(), GenCamError> {
+        let exposure = Duration::from_micros(self.exposure.load(Ordering::SeqCst));
+        self.start_exposure()?;
+        sleep(exposure);
+        if !self.wait_ready(Duration::from_secs(10))? {
+            // exposure was cancelled
+            return Err(GenCamError::ExposureNotStarted);
+        }
+        Ok(())
+    }
+
+    /// Capture a frame with the current settings.
+    fn capture_one(&mut self) -> Result<GenericImage, GenCamError> {
+        self.expose()?;
+        Ok(self.download_image()?.into())
+    }
+
+    /// Capture a frame at `exposure` and `gain` (raw SDK units), then restore the
+    /// previous exposure and gain, whether or not the capture succeeded.
+    pub fn capture_with(
         &mut self,
-        prop: &GenCamCtrl,
-        value: &PropertyValue,
-        auto: bool,
-    ) -> Result<(), GenCamError> {
-        if !self.sensor_ctrl.contains(prop) & !self.device_ctrl.contains(prop) {
-            return Err(GenCamError::PropertyError {
-                control: *prop,
-                error: PropertyError::NotFound,
+        exposure: Duration,
+        gain: i64,
+    ) -> Result<GenericImage, GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let control: GenCamCtrl = AnalogCtrl::Gain.into();
+        let perr = |error: PropertyError| GenCamError::PropertyError { control, error };
+        let (_, lims) = self
+            .sensor_ctrl
+            .get_controller(&control)
+            .ok_or(perr(PropertyError::NotFound))?;
+        lims.validate(&PropertyValue::Float(gain as f64 * 0.1))
+            .map_err(perr)?;
+        let handle = self.handle.handle();
+        let (prev_exposure, prev_auto) = self.get_exposure()?;
+        let (prev_gain, prev_gain_auto) = get_control_value(handle, AsiControlType::Gain)?;
+        let res = self
+            .set_exposure(exposure, false)
+            .and_then(|_| self.set_gain(gain))
+            .and_then(|_| self.capture_one());
+        let restored = self
+            .set_exposure(prev_exposure, prev_auto)
+            .and_then(|_| self.set_gain(prev_gain))
+            .and_then(|_| {
+                if prev_gain_auto == ASI_BOOL_ASI_TRUE as _ {
+                    set_control_value(handle, AsiControlType::Gain, prev_gain, ASI_BOOL_ASI_TRUE)
+                } else {
+                    Ok(())
+                }
+            });
+        match (res, restored) {
+            (Ok(img), Ok(())) => Ok(img),
+            (Ok(_), Err(e)) => Err(e),
+            (Err(e), restored) => {
+                if let Err(re) = restored {
+                    warn!("Failed to restore exposure and gain: {:?}", re);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub fn capture_flat(
+        &mut self,
+        target_fraction: f32,
+        max_exp: Duration,
+    ) -> Result<GenericImage, GenCamError> {
+        if !(target_fraction > 0.0 && target_fraction < 1.0) {
+            return Err(GenCamError::GeneralError(format!(
+                "Flat frame target {} is not between 0 and 1",
+                target_fraction
+            )));
+        }
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let (min, max) = self.exposure_range()?;
+        let max = max.min(max_exp);
+        if max < min {
+            return Err(GenCamError::GeneralError(format!(
+                "Maximum flat exposure {:?} is below the minimum exposure {:?}",
+                max_exp, min
+            )));
+        }
+        let (prev_exposure, prev_auto) = self.get_exposure()?;
+        let res = self.converge_flat(
+            target_fraction as f64,
+            prev_exposure.clamp(min, max),
+            min,
+            max,
+        );
+        let restored = self.set_exposure(prev_exposure, prev_auto);
+        match (res, restored) {
+            (Ok(img), Ok(())) => Ok(img),
+            (Ok(_), Err(e)) => Err(e),
+            (Err(e), restored) => {
+                if let Err(re) = restored {
+                    warn!("Failed to restore exposure: {:?}", re);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Capture frames, adjusting the exposure until the mean is within
+    /// [`FLAT_TOLERANCE`] of `target` (fraction of full scale).
+    fn converge_flat(
+        &mut self,
+        target: f64,
+        mut exposure: Duration,
+        min: Duration,
+        max: Duration,
+    ) -> Result<GenericImage, GenCamError> {
+        for _ in 0..FLAT_MAX_ITERATIONS {
+            self.set_exposure(exposure, false)?;
+            let mut img = self.capture_one()?;
+            let full_scale = match self.last_frame {
+                Some((_, GenCamPixelBpp::Bpp8)) => u8::MAX as f64,
+                _ => self.saturation_level() as f64,
+            };
+            let Some(stats) = self.frame_stats() else {
+                return Err(GenCamError::InvalidFormat("Empty frame".into()));
+            };
+            let fraction = stats.mean / full_scale;
+            if (fraction - target).abs() <= FLAT_TOLERANCE * target {
+                img.insert_key("IMAGETYP", ("Flat", "Frame type"));
+                return Ok(img);
+            }
+            let next = next_flat_exposure(exposure, fraction, target, min, max);
+            if next == exposure {
+                return Err(GenCamError::GeneralError(format!(
+                    "Flat frame mean at {:.1}% of full scale, exposure limited to {:?}",
+                    fraction * 100.0,
+                    exposure
+                )));
+            }
+            exposure = next;
+        }
+        Err(GenCamError::GeneralError(format!(
+            "Flat frame mean did not reach {:.1}% of full scale in {} frames",
+            target * 100.0,
+            FLAT_MAX_ITERATIONS
+        )))
+    }
+
+    pub fn get_property(&self, prop: &GenCamCtrl) -> Result<(PropertyValue, bool), GenCamError> {
+        if !self.sensor_ctrl.contains(prop) & !self.device_ctrl.contains(prop) {
+            return Err(GenCamError::PropertyError {
+                control: *prop,
+                error: PropertyError::NotFound,
+            });
+        };
+        match prop {
+            GenCamCtrl::Exposure(ExposureCtrl::ExposureTime) => {
+                let (exp, auto) = self.get_exposure()?;
+                Ok((PropertyValue::from(exp), auto))
+            }
+            GenCamCtrl::Sensor(SensorCtrl::PixelFormat) => {
+                let val: GenCamPixelBpp = (self.roi.1);
+                Ok((PropertyValue::PixelFmt(val), false))
+            }
+            GenCamCtrl::Sensor(SensorCtrl::ReverseX) => {
+                let (flipx, _) = self.get_flip()?;
+                Ok((PropertyValue::Bool(flipx), false))
+            }
+            GenCamCtrl::Sensor(SensorCtrl::ReverseY) => {
+                let (_, flipy) = self.get_flip()?;
+                Ok((PropertyValue::Bool(flipy), false))
+            }
+            GenCamCtrl::Sensor(SensorCtrl::ShutterMode) => {
+                if let Some(open) = &self.shutter_open {
+                    Ok((PropertyValue::Bool(open.load(Ordering::SeqCst)), false))
+                } else {
+                    Err(GenCamError::PropertyError {
+                        control: *prop,
+                        error: PropertyError::NotFound,
+                    })
+                }
+            }
+            GenCamCtrl::Analog(AnalogCtrl::Gain) => {
+                let val = self.get_gain()?;
+                Ok((PropertyValue::from(val as f64 * 0.1), false))
+            }
+            _ => self.device_ctrl.get_value(&self.handle, prop),
+        }
+    }
+
+    /// Set a property.
+    ///
+    /// Sensor properties return [`GenCamError::ExposureInProgress`] during a capture,
+    /// except the auto-exposure limits ([`ExposureCtrl::AutoMaxExposure`],
+    /// [`ExposureCtrl::AutoTargetBrightness`] and [`ExposureCtrl::AutoMaxGain`]),
+    /// which take effect on subsequent frames. Device properties can always be set.
+    pub fn set_property(
+        &mut self,
+        prop: &GenCamCtrl,
+        value: &PropertyValue,
+        auto: bool,
+    ) -> Result<(), GenCamError> {
+        if *prop == uuid_ctrl() {
+            return self.set_uuid(value);
+        }
+        if !self.sensor_ctrl.contains(prop) & !self.device_ctrl.contains(prop) {
+            return Err(GenCamError::PropertyError {
+                control: *prop,
+                error: PropertyError::NotFound,
             });
         };
         let (ctrl, lims) = {
             match prop {
                 GenCamCtrl::Device(ctrl) => {
                     if ctrl == &DeviceCtrl::CoolerTemp {
+                        // a rejected target must not turn the cooler on
+                        self.device_ctrl.check_value(prop, value)?;
                         ASICALL!(ASISetControlValue(
                             self.handle.handle(),
                             ASI_CONTROL_TYPE_ASI_COOLER_ON as _,
@@ -709,7 +1735,7 @@ impl AsiImager {
                             AsiError::InvalidId(_, _) => {
                                 GenCamError::InvalidId(self.handle.handle())
                             }
-                            _ => GenCamError::GeneralError(format!("{:?}", e)),
+                            _ => GenCamError::GeneralError(e.to_string()),
                         })?;
                     }
                     return self.device_ctrl.set_value(&self.handle, prop, value, auto);
@@ -723,6 +1749,13 @@ impl AsiImager {
                     })?,
             }
         };
+        // read-only controls are rejected before reaching the SDK
+        if lims.is_readonly() {
+            return Err(GenCamError::PropertyError {
+                control: *prop,
+                error: PropertyError::ReadOnly,
+            });
+        }
         // validation skip
         match prop {
             GenCamCtrl::Sensor(SensorCtrl::PixelFormat) => {}
@@ -737,7 +1770,10 @@ impl AsiImager {
             }
         }
         let handle = self.handle.handle();
-        // handle the sensor controls that don't need lock
+        // handle the sensor controls that don't need lock: the auto-exposure
+        // limits only steer the SDK's exposure/gain adjustment between frames,
+        // and never alter the exposure in progress, so they are safe to change
+        // during a capture
         match ctrl {
             AsiControlType::AutoExpMax
             | AsiControlType::AutoExpTarget
@@ -759,18 +1795,10 @@ impl AsiImager {
         match prop {
             GenCamCtrl::Sensor(SensorCtrl::PixelFormat) => {
                 if let PropertyValue::PixelFmt(fmt) = value {
-                    if [GenCamPixelBpp::Bpp8, GenCamPixelBpp::Bpp16].contains(fmt) {
-                        let roi = AsiRoi::concat(&self.roi.0, *fmt);
-                        self.set_roi_raw(&roi)?;
-                        let info = get_info(handle)?;
-                        self.e2d = info.ElecPerADU as _; // total number of electrons
-                        Ok(())
-                    } else {
-                        Err(GenCamError::PropertyError {
-                            control: *prop,
-                            error: PropertyError::ValueNotSupported,
-                        })
-                    }
+                    // rejects formats the camera does not support, instead of
+                    // falling back to RAW8, and keeps the binning
+                    let roi = self.roi.0;
+                    self.set_roi_and_format(&roi, *fmt)
                 } else {
                     Err(GenCamError::PropertyError {
                         control: *prop,
@@ -841,6 +1869,37 @@ impl AsiImager {
         }
     }
 
+    /// Write the UUID (exactly 8 bytes) to the flash of a USB3 camera.
+    fn set_uuid(&self, value: &PropertyValue) -> Result<(), GenCamError> {
+        let control = uuid_ctrl();
+        // the UUID control is only registered for USB3 cameras
+        if !self.device_ctrl.contains(&control) {
+            return Err(GenCamError::InvalidControlType(
+                "UUID can only be set on USB3 cameras".into(),
+            ));
+        }
+        let PropertyValue::EnumStr(uuid) = value else {
+            return Err(GenCamError::PropertyError {
+                control,
+                error: PropertyError::ValueNotSupported,
+            });
+        };
+        let mut id = ASI_ID::default();
+        if uuid.len() != id.id.len() {
+            return Err(GenCamError::PropertyError {
+                control,
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        id.id[..uuid.len()].copy_from_slice(uuid.as_bytes());
+        let handle = self.handle.handle();
+        ASICALL!(ASISetID(handle, id)).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })
+    }
+
     fn get_flip(&self) -> Result<(bool, bool), GenCamError> {
         let handle = self.handle.handle();
         let mut flip = Default::default();
@@ -854,10 +1913,8 @@ impl AsiImager {
         .map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            AsiError::InvalidControlType(src, args) => {
-                GenCamError::InvalidControlType(format!("{src:?}(args: {args:?})"))
-            }
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            AsiError::InvalidControlType(_, _) => GenCamError::InvalidControlType(e.to_string()),
+            _ => GenCamError::GeneralError(e.to_string()),
         })?;
         let flip = flip as _;
         Ok(match flip {
@@ -891,11 +1948,275 @@ impl AsiImager {
         .map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            AsiError::InvalidControlType(src, args) => {
-                GenCamError::InvalidControlType(format!("{src:?}(args: {args:?})"))
+            AsiError::InvalidControlType(_, _) => GenCamError::InvalidControlType(e.to_string()),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })?;
+        Ok(())
+    }
+
+    pub fn export_settings(&self) -> Result<CameraSettings, GenCamError> {
+        let (exposure, exposure_auto) = self.get_exposure()?;
+        let gain = if self.sensor_ctrl.contains(&AnalogCtrl::Gain.into()) {
+            Some(self.get_gain()? as f64 * 0.1)
+        } else {
+            None
+        };
+        let cooler = GenCamCtrl::Device(DeviceCtrl::CoolerTemp);
+        let cooler_target = if self.device_ctrl.contains(&cooler) {
+            let (val, _) = self.device_ctrl.get_value(&self.handle, &cooler)?;
+            Some(val.try_into().map_err(|e| GenCamError::PropertyError {
+                control: cooler,
+                error: e,
+            })?)
+        } else {
+            None
+        };
+        let handle = self.handle.handle();
+        let raw = |ctype| {
+            self.raw_caps(ctype)
+                .map(|_| get_control_value(handle, ctype).map(|(val, _)| val))
+                .transpose()
+        };
+        Ok(CameraSettings {
+            exposure,
+            exposure_auto,
+            gain,
+            offset: raw(AsiControlType::Offset)?,
+            bandwidth: raw(AsiControlType::BWOvld)?,
+            bin: self.binning(),
+            roi: self.roi.0,
+            pixel_format: self.roi.1,
+            flip: self.get_flip()?,
+            cooler_target,
+        })
+    }
+
+    /// Read the auto state of every control that supports auto mode.
+    pub fn auto_states(&self) -> Result<HashMap<GenCamCtrl, bool>, GenCamError> {
+        let handle = self.handle.handle();
+        let mut out = HashMap::new();
+        let props = self
+            .sensor_ctrl
+            .list_properties()
+            .iter()
+            .chain(self.device_ctrl.list_properties());
+        for (ctrl, prop) in props {
+            if !prop.auto_supported() {
+                continue;
+            }
+            let Some((ctype, _)) = self
+                .sensor_ctrl
+                .get_controller(ctrl)
+                .or_else(|| self.device_ctrl.get_controller(ctrl))
+            else {
+                continue;
+            };
+            let (_, auto) = get_control_value(handle, *ctype)?;
+            out.insert(*ctrl, auto == ASI_BOOL_ASI_TRUE as _);
+        }
+        Ok(out)
+    }
+
+    /// Apply the settings, restoring the previous settings if any of them fail.
+    pub fn import_settings(&mut self, settings: &CameraSettings) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let backup = self.export_settings()?;
+        if let Err(e) = self.apply_settings(settings) {
+            if let Err(e) = self.apply_settings(&backup) {
+                warn!("Failed to restore camera settings: {:?}", e);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn apply_preset(&mut self, preset: &Preset) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let mut backup = Vec::with_capacity(preset.controls().len());
+        for (ctrl, value, auto) in preset.controls() {
+            let res = self
+                .get_property(ctrl)
+                .and_then(|prev| self.set_property(ctrl, value, *auto).map(|_| prev));
+            match res {
+                Ok((prev, prev_auto)) => backup.push((*ctrl, prev, prev_auto)),
+                Err(e) => {
+                    for (ctrl, value, auto) in backup.iter().rev() {
+                        if let Err(e) = self.set_property(ctrl, value, *auto) {
+                            warn!("Failed to restore {:?}: {:?}", ctrl, e);
+                        }
+                    }
+                    return Err(e);
+                }
             }
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+        }
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, settings: &CameraSettings) -> Result<(), GenCamError> {
+        self.set_property(
+            &SensorCtrl::PixelFormat.into(),
+            &settings.pixel_format.into(),
+            false,
+        )?;
+        // the ROI is in binned pixels, so the binning goes first
+        if settings.bin != self.binning() {
+            self.set_binning(settings.bin)?;
+        }
+        self.set_roi(&settings.roi)?;
+        self.set_property(
+            &ExposureCtrl::ExposureTime.into(),
+            &settings.exposure.into(),
+            settings.exposure_auto,
+        )?;
+        if let Some(gain) = settings.gain {
+            self.set_property(&AnalogCtrl::Gain.into(), &gain.into(), false)?;
+        }
+        if let Some(offset) = settings.offset {
+            self.set_raw_control(AsiControlType::Offset, offset)?;
+        }
+        if let Some(bandwidth) = settings.bandwidth {
+            self.set_raw_control(AsiControlType::BWOvld, bandwidth)?;
+        }
+        let (flipx, flipy) = settings.flip;
+        self.set_flip(flipx, flipy)?;
+        if let Some(target) = settings.cooler_target {
+            // does not turn the cooler on, unlike `set_property`
+            self.device_ctrl.set_value(
+                &self.handle,
+                &DeviceCtrl::CoolerTemp.into(),
+                &PropertyValue::Int(target),
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Get the caps of a control, including controls without a property, e.g. the offset.
+    fn raw_caps(&self, ctype: AsiControlType) -> Option<&ASI_CONTROL_CAPS> {
+        self.ctrl_caps
+            .iter()
+            .find(|cap| AsiControlType::from(cap.ControlType) == ctype)
+    }
+
+    /// Set a manual value of a control without a property, within the range of the control.
+    fn set_raw_control(&self, ctype: AsiControlType, value: i64) -> Result<(), GenCamError> {
+        let cap = self
+            .raw_caps(ctype)
+            .ok_or_else(|| GenCamError::InvalidControlType(format!("{:?}", ctype)))?;
+        if value < cap.MinValue as i64 || value > cap.MaxValue as i64 {
+            return Err(GenCamError::PropertyError {
+                control: DeviceCtrl::Custom(string_from_char(&cap.Name).as_str().into()).into(),
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        set_control_value(self.handle.handle(), ctype, value, ASI_BOOL_ASI_FALSE as _)
+    }
+
+    pub fn supported_modes(&self) -> Result<Vec<AsiCameraMode>, GenCamError> {
+        if !self.is_trigger {
+            return Err(GenCamError::InvalidControlType(
+                "Camera does not support trigger modes".into(),
+            ));
+        }
+        let handle = self.handle.handle();
+        let mut modes = ASI_SUPPORTED_MODE::default();
+        ASICALL!(ASIGetCameraSupportMode(handle, &mut modes)).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })?;
+        Ok(modes
+            .SupportedCameraMode
+            .iter()
+            .take_while(|x| **x != ASI_CAMERA_MODE_ASI_MODE_END)
+            .filter_map(|x| AsiCameraMode::try_from(*x).ok())
+            .collect())
+    }
+
+    pub fn get_mode(&self) -> Result<AsiCameraMode, GenCamError> {
+        if !self.is_trigger {
+            return Ok(AsiCameraMode::Normal);
+        }
+        let handle = self.handle.handle();
+        let mut mode = Default::default();
+        ASICALL!(ASIGetCameraMode(handle, &mut mode)).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })?;
+        AsiCameraMode::try_from(mode)
+    }
+
+    pub fn set_mode(&self, mode: AsiCameraMode) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        if !self.supported_modes()?.contains(&mode) {
+            return Err(GenCamError::InvalidControlType(format!(
+                "Camera mode {:?} is not supported",
+                mode
+            )));
+        }
+        let handle = self.handle.handle();
+        ASICALL!(ASISetCameraMode(handle, mode as _)).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            AsiError::InvalidMode(_, _) => GenCamError::InvalidControlType(e.to_string()),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })
+    }
+
+    pub fn set_max_pulse_duration(&mut self, max: Duration) {
+        self.max_pulse = max;
+    }
+
+    pub fn max_pulse_duration(&self) -> Duration {
+        self.max_pulse
+    }
+
+    /// Start an ST4 guide pulse, and schedule its end after `duration`.
+    ///
+    /// Returns as soon as the pulse has started; `ASIPulseGuideOff` is issued
+    /// from a background thread, unless another pulse was started in the same
+    /// direction in the meantime, or the camera was closed.
+    pub fn pulse_guide(&self, dir: GuideDir, duration: Duration) -> Result<(), GenCamError> {
+        if !self.has_st4 {
+            return Err(GenCamError::InvalidControlType(
+                "Camera does not have an ST4 port".into(),
+            ));
+        }
+        if duration.is_zero() || duration > self.max_pulse {
+            return Err(GenCamError::PropertyError {
+                control: DeviceCtrl::Custom("PulseGuide".into()).into(),
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        let handle = self.handle.handle();
+        ASICALL!(ASIPulseGuideOn(handle, dir.into())).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
         })?;
+        let pulses = self.pulses.clone();
+        let generation = pulses[dir as usize].fetch_add(1, Ordering::SeqCst) + 1;
+        let weak = self.weak_handle();
+        std::thread::spawn(move || {
+            sleep(duration);
+            // a newer pulse in this direction ends itself
+            if pulses[dir as usize].load(Ordering::SeqCst) != generation {
+                return;
+            }
+            let Some(handle) = weak.upgrade() else {
+                return;
+            };
+            if let Err(e) = ASICALL!(ASIPulseGuideOff(handle.handle(), dir.into())) {
+                warn!("Could not end {:?} guide pulse: {}", dir, e);
+            }
+        });
         Ok(())
     }
 
@@ -927,61 +2248,735 @@ impl AsiImager {
         }
     }
 
+    /// Poll until the image is ready, or `timeout` expires.
+    ///
+    /// The poll interval depends on the exposure length: 1 ms for exposures
+    /// under 1 s, 100 ms for exposures under 10 s, and 1 s otherwise.
+    /// Returns `Ok(false)` if the exposure was cancelled while waiting.
+    pub fn wait_ready(&self, timeout: Duration) -> GenCamResult<bool> {
+        let exposure = Duration::from_micros(self.exposure.load(Ordering::SeqCst));
+        let interval = if exposure < Duration::from_secs(1) {
+            Duration::from_millis(1)
+        } else if exposure < Duration::from_secs(10) {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(1)
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.capturing.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+            if self.image_ready()? {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(GenCamError::TimedOut);
+            }
+            sleep(interval.min(deadline - now));
+        }
+    }
+
     pub fn camera_name(&self) -> &str {
         str::from_utf8(&self.name)
             .unwrap_or("")
             .trim_end_matches(char::from(0))
     }
 
+    pub fn serial_number(&self) -> &str {
+        str::from_utf8(&self.serial)
+            .unwrap_or("")
+            .trim_end_matches(char::from(0))
+    }
+
     pub fn is_capturing(&self) -> bool {
         self.capturing.load(Ordering::SeqCst)
     }
 
-    pub fn set_roi(&mut self, roi: &GenCamRoi) -> Result<&GenCamRoi, GenCamError> {
-        if self.is_capturing() {
-            return Err(GenCamError::ExposureInProgress);
-        }
-        let roi = AsiRoi::concat(roi, self.roi.1);
-        self.set_roi_raw(&roi)?;
-        Ok(&self.roi.0)
+    pub fn is_color(&self) -> bool {
+        self.cspace != ColorSpace::Gray
     }
 
-    pub fn get_roi(&self) -> &GenCamRoi {
-        &self.roi.0
+    /// Compute the mean pixel values in the corners and the center of the last
+    /// downloaded frame. Each region spans 1/8 of the frame in each dimension.
+    pub fn corner_statistics(&self) -> Option<CornerStats> {
+        let (roi, bpp) = self.last_frame?;
+        let (width, height) = (roi.width as usize, roi.height as usize);
+        let region = (width / 8, height / 8);
+        match bpp {
+            GenCamPixelBpp::Bpp8 => {
+                let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+                corner_stats(data, width, height, region)
+            }
+            _ => corner_stats(&self.imgstor, width, height, region),
+        }
     }
 
-    pub fn get_concat_caps(&self) -> HashMap<GenCamCtrl, Property> {
-        let mut out = self.sensor_ctrl.list_properties().clone();
-        out.extend(self.device_ctrl.list_properties().clone());
-        out
+    /// Compute the minimum, maximum, mean and standard deviation of the pixel
+    /// values of the last downloaded frame, in a single pass.
+    pub fn frame_stats(&self) -> Option<FrameStats> {
+        let (roi, bpp) = self.last_frame?;
+        let npix = roi.width as usize * roi.height as usize;
+        match bpp {
+            GenCamPixelBpp::Bpp8 => {
+                let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+                frame_stats(&data[..npix], u8::MAX as _)
+            }
+            _ => frame_stats(&self.imgstor[..npix], self.saturation_level()),
+        }
     }
 
-    pub fn get_info_handle(&self) -> GenCamInfoAsi {
-        GenCamInfoAsi {
-            handle: self.handle.clone(),
-            serial: self.serial,
-            name: self.name,
-            has_cooler: self.has_cooler,
-            capturing: self.capturing.clone(),
-            expstart: self.expstart.clone(),
-            info: self.info.clone(),
-            ctrl: self.device_ctrl.clone(),
+    /// Get the largest value of a 16-bit pixel.
+    fn saturation_level(&self) -> u16 {
+        // the sensor saturates below 0xffff if its bit depth is lower than 16
+        let shift = 16u8.saturating_sub(self.bitdepth);
+        match self.alignment {
+            DataAlignment::LeftShifted => (u16::MAX >> shift) << shift,
+            DataAlignment::RightAligned => u16::MAX >> shift,
         }
     }
 
-    pub fn get_descriptor(&self) -> &GenCamDescriptor {
-        &self.info
+    pub fn is_overexposed(&self, threshold_fraction: f32) -> Option<bool> {
+        self.frame_stats()
+            .map(|stats| stats.saturated > threshold_fraction as f64)
     }
-}
 
-impl GenCamInfo for GenCamInfoAsi {
-    fn camera_ready(&self) -> bool {
-        true
+    /// Copy the pixel data of the last downloaded frame as bytes.
+    ///
+    /// 16-bit pixels are written in native byte order, or in big-endian order
+    /// (e.g. for FITS) if `big_endian` is set.
+    pub fn frame_bytes(&self, big_endian: bool) -> Option<Vec<u8>> {
+        let (roi, bpp) = self.last_frame?;
+        let npix = roi.width as usize * roi.height as usize;
+        match bpp {
+            GenCamPixelBpp::Bpp8 => {
+                let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+                Some(data[..npix].to_vec())
+            }
+            _ if big_endian => Some(
+                self.imgstor[..npix]
+                    .iter()
+                    .flat_map(|px| px.to_be_bytes())
+                    .collect(),
+            ),
+            _ => Some(bytemuck::cast_slice(&self.imgstor[..npix]).to_vec()),
+        }
     }
 
-    fn camera_name(&self) -> &str {
-        str::from_utf8(&self.name)
-            .unwrap_or("")
+    /// Write each downloaded frame to the shared-memory ring `name`, with `slots` slots.
+    /// Each slot fits a full-sensor 16-bit frame.
+    #[cfg(feature = "shmem")]
+    pub fn stream_to_shmem(&mut self, name: &str, slots: usize) -> Result<(), GenCamError> {
+        let (width, height) = self.sensor_size;
+        let size = width as usize * height as usize * size_of::<u16>();
+        // the old ring removes its file when dropped, which may be the new one
+        self.shmem = None;
+        let ring = ShmemRing::create(name, slots, size).map_err(|e| {
+            GenCamError::GeneralError(format!("Shared memory ring {}: {}", name, e))
+        })?;
+        self.shmem = Some(ring);
+        Ok(())
+    }
+
+    /// Stop writing frames to the shared-memory ring, and remove it.
+    #[cfg(feature = "shmem")]
+    pub fn stop_shmem_stream(&mut self) {
+        self.shmem = None;
+    }
+
+    #[cfg(feature = "shmem")]
+    fn write_shmem(&mut self) {
+        let (Some(ring), Some((roi, bpp))) = (self.shmem.as_mut(), self.last_frame) else {
+            return;
+        };
+        let npix = roi.width as usize * roi.height as usize;
+        let bytes_per_pixel = match bpp {
+            GenCamPixelBpp::Bpp8 => 1,
+            _ => 2,
+        };
+        let data: &[u8] = bytemuck::cast_slice(&self.imgstor);
+        if let Err(e) = ring.write(
+            roi.width as _,
+            roi.height as _,
+            bytes_per_pixel,
+            &data[..npix * bytes_per_pixel as usize],
+        ) {
+            warn!("Failed to write frame to shared memory: {:?}", e);
+        }
+    }
+
+    /// Estimate the maximum frame rate for the current ROI and pixel format,
+    /// limited by the USB link and the bandwidth setting. Exposure time and
+    /// sensor readout are not accounted for.
+    pub fn estimated_max_fps(&self) -> f32 {
+        let (roi, bpp) = &self.roi;
+        let bytes_per_px = match bpp {
+            GenCamPixelBpp::Bpp16 => 2.0,
+            _ => 1.0,
+        };
+        let frame_bytes = roi.width as f32 * roi.height as f32 * bytes_per_px;
+        if frame_bytes == 0.0 {
+            return 0.0;
+        }
+        let bandwidth = get_control_value(self.handle.handle(), AsiControlType::BWOvld)
+            .map(|(val, _)| val as f32 / 100.0)
+            .unwrap_or(1.0);
+        let rate = if self.usb3 {
+            USB3_BYTES_PER_SEC
+        } else {
+            USB2_BYTES_PER_SEC
+        };
+        rate * bandwidth / frame_bytes
+    }
+
+    /// Apply the manufacturer default of `control`, as a manual value.
+    pub fn reset_to_default(&mut self, control: &GenCamCtrl) -> Result<(), GenCamError> {
+        let perr = |error: PropertyError| GenCamError::PropertyError {
+            control: *control,
+            error,
+        };
+        let (_, lims) = self
+            .sensor_ctrl
+            .get_controller(control)
+            .or_else(|| self.device_ctrl.get_controller(control))
+            .ok_or(perr(PropertyError::NotFound))?;
+        let default = lims.get_default().map_err(perr)?;
+        self.set_property(control, &default, false)
+    }
+
+    /// Get the manufacturer default offset, if the camera has an offset control.
+    pub fn default_offset(&self) -> Option<i64> {
+        self.raw_caps(AsiControlType::Offset)
+            .map(|cap| cap.DefaultValue as _)
+    }
+
+    /// Convert a pixel value (ADU) to electrons, using the gain reported by the SDK.
+    pub fn adu_to_electrons(&self, adu: f64) -> f64 {
+        adu * self.e2d as f64
+    }
+
+    /// Estimate the dynamic range (stops) from the full well, approximated as
+    /// e-/ADU x 2^bitdepth, over a noise floor of one electron or one ADU,
+    /// whichever is larger.
+    pub fn dynamic_range_stops(&self) -> Option<f32> {
+        if self.bitdepth == 0 || self.e2d.is_nan() || self.e2d <= 0.0 {
+            return None;
+        }
+        // log2(e2d * 2^bitdepth / max(1, e2d))
+        Some(self.bitdepth as f32 + self.e2d.min(1.0).log2())
+    }
+
+    pub fn set_timestamp_source(&mut self, source: TimestampSource) {
+        self.tstamp_source = source;
+    }
+
+    pub fn timestamp_source(&self) -> TimestampSource {
+        self.tstamp_source
+    }
+
+    pub fn set_data_alignment(&mut self, alignment: DataAlignment) {
+        self.alignment = alignment;
+    }
+
+    pub fn data_alignment(&self) -> DataAlignment {
+        self.alignment
+    }
+
+    pub fn gain_info(&self) -> Result<GainInfo, GenCamError> {
+        let control: GenCamCtrl = AnalogCtrl::Gain.into();
+        let perr = |error: PropertyError| GenCamError::PropertyError { control, error };
+        let (_, lims) = self
+            .sensor_ctrl
+            .get_controller(&control)
+            .ok_or(perr(PropertyError::NotFound))?;
+        let min: f64 = lims.get_min().map_err(perr)?.try_into().map_err(perr)?;
+        let max: f64 = lims.get_max().map_err(perr)?.try_into().map_err(perr)?;
+        let handle = self.handle.handle();
+        let (raw, auto) = get_control_value(handle, AsiControlType::Gain)?;
+        if let Ok(mut gain) = self.gain.try_borrow_mut() {
+            *gain = Some(raw);
+        }
+        let db = raw as f64 * 0.1;
+        let percent = if max > min {
+            ((db - min) / (max - min) * 100.0) as f32
+        } else {
+            0.0
+        };
+        Ok(GainInfo {
+            raw,
+            db,
+            percent,
+            auto: auto == ASI_BOOL_ASI_TRUE as _,
+        })
+    }
+
+    /// Get the range (min, max) of the auto-exposure target brightness, in raw units.
+    fn auto_target_range(&self) -> Result<(i64, i64), GenCamError> {
+        let control: GenCamCtrl = ExposureCtrl::AutoTargetBrightness.into();
+        let perr = |error: PropertyError| GenCamError::PropertyError { control, error };
+        let (_, lims) = self
+            .sensor_ctrl
+            .get_controller(&control)
+            .ok_or(perr(PropertyError::NotFound))?;
+        let min: i64 = lims.get_min().map_err(perr)?.try_into().map_err(perr)?;
+        let max: i64 = lims.get_max().map_err(perr)?.try_into().map_err(perr)?;
+        Ok((min, max))
+    }
+
+    pub fn set_auto_target_percent(&mut self, percent: f32) -> Result<(), GenCamError> {
+        let control: GenCamCtrl = ExposureCtrl::AutoTargetBrightness.into();
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(GenCamError::PropertyError {
+                control,
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        let (min, max) = self.auto_target_range()?;
+        let raw = percent_to_raw(percent, min, max);
+        self.set_property(&control, &PropertyValue::Int(raw), false)
+    }
+
+    pub fn auto_target_percent(&self) -> Result<f32, GenCamError> {
+        let (min, max) = self.auto_target_range()?;
+        let (raw, _) = get_control_value(self.handle.handle(), AsiControlType::AutoExpTarget)?;
+        Ok(raw_to_percent(raw, min, max))
+    }
+
+    /// Set the ROI, and return the ROI applied by the camera, which may differ
+    /// from `roi` if the SDK aligns it.
+    pub fn set_roi(&mut self, roi: &GenCamRoi) -> Result<&GenCamRoi, GenCamError> {
+        self.set_roi_and_format(roi, self.roi.1)?;
+        Ok(&self.roi.0)
+    }
+
+    /// Set the ROI and the pixel format with a single SDK call.
+    pub fn set_roi_and_format(
+        &mut self,
+        roi: &GenCamRoi,
+        fmt: GenCamPixelBpp,
+    ) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        if ![GenCamPixelBpp::Bpp8, GenCamPixelBpp::Bpp16].contains(&fmt)
+            || !self.formats.contains(&fmt)
+        {
+            return Err(GenCamError::PropertyError {
+                control: GenCamCtrl::Sensor(SensorCtrl::PixelFormat),
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        let format_change = fmt != self.roi.1;
+        let mut roi = AsiRoi::concat(roi, fmt);
+        roi.bin = self.bin;
+        // the binned window must fit inside the binned sensor
+        let (max_width, max_height) = self.sensor_size;
+        let (max_width, max_height) = (max_width / roi.bin, max_height / roi.bin);
+        if roi.x + roi.width > max_width || roi.y + roi.height > max_height {
+            return Err(GenCamError::PropertyError {
+                control: roi_ctrl(),
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        self.set_roi_raw(&roi)?;
+        if format_change {
+            let info = get_info(self.handle.handle())?;
+            self.e2d = info.ElecPerADU as _;
+        }
+        Ok(())
+    }
+
+    /// Set a `width` x `height` window (in binned pixels) centered on the sensor.
+    ///
+    /// The SDK requires the width to be a multiple of 8 and the height to be a
+    /// multiple of 2, so the window is shrunk to satisfy these constraints, and
+    /// the origin is rounded down to an even pixel to preserve the Bayer pattern.
+    pub fn set_centered_roi(
+        &mut self,
+        width: u32,
+        height: u32,
+        bin: u32,
+    ) -> Result<&GenCamRoi, GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let (max_width, max_height) = self.max_roi_for_bin(bin)?;
+        let width = width & !7;
+        let height = height & !1;
+        if width == 0 || height == 0 || width > max_width || height > max_height {
+            return Err(GenCamError::GeneralError(format!(
+                "ROI {}x{} does not fit in the {}x{} sensor at bin {}",
+                width, height, max_width, max_height, bin
+            )));
+        }
+        let (width, height, bin) = (width as i32, height as i32, bin as i32);
+        let (max_width, max_height) = self.sensor_size;
+        let (max_width, max_height) = (max_width / bin, max_height / bin);
+        let roi = AsiRoi {
+            x: ((max_width - width) / 2) & !1,
+            y: ((max_height - height) / 2) & !1,
+            width,
+            height,
+            bin,
+            fmt: AsiRoi::concat(&self.roi.0, self.roi.1).fmt,
+        };
+        self.set_roi_raw(&roi)?;
+        Ok(&self.roi.0)
+    }
+
+    pub fn binning(&self) -> u32 {
+        self.bin as _
+    }
+
+    /// Change the binning, keeping the current ROI on the same area of the sensor.
+    ///
+    /// The window is shrunk to a width that is a multiple of 8 and an even height,
+    /// no smaller than 8x2, and moved if needed to fit inside the binned sensor.
+    pub fn set_binning(&mut self, bin: u32) -> Result<(), GenCamError> {
+        if self.is_capturing() {
+            return Err(GenCamError::ExposureInProgress);
+        }
+        let (max_width, max_height) = self.max_roi_for_bin(bin)?;
+        let (old, new) = (self.bin as u32, bin);
+        let roi = &self.roi.0;
+        // scale through unbinned sensor coordinates
+        // the SDK rejects windows smaller than 8x2
+        let width = (roi.width as u32 * old / new).max(8).min(max_width) & !7;
+        let height = (roi.height as u32 * old / new).max(2).min(max_height) & !1;
+        let x = (roi.x_min as u32 * old / new).min(max_width - width);
+        let y = (roi.y_min as u32 * old / new).min(max_height - height);
+        let roi = AsiRoi {
+            x: x as _,
+            y: y as _,
+            width: width as _,
+            height: height as _,
+            bin: bin as _,
+            fmt: AsiRoi::concat(&self.roi.0, self.roi.1).fmt,
+        };
+        self.set_roi_raw(&roi)
+    }
+
+    pub fn supported_formats(&self) -> &[GenCamPixelBpp] {
+        &self.formats
+    }
+
+    pub fn has_mechanical_shutter(&self) -> bool {
+        self.shutter_open.is_some()
+    }
+
+    pub fn usb_connection_speed(&self) -> Option<UsbSpeed> {
+        let info = get_info(self.handle.handle()).ok()?;
+        Some(UsbSpeed::from_info(&info))
+    }
+
+    pub fn last_frame_metadata(&self) -> Option<&FrameMetadata> {
+        self.last_metadata.as_ref()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn last_frame_metadata_json(&self) -> Option<String> {
+        let meta = self.last_metadata.as_ref()?;
+        serde_json::to_string(meta)
+            .map_err(|e| warn!("Could not serialize frame metadata: {}", e))
+            .ok()
+    }
+
+    /// Get the largest ROI (width, height) at binning `bin`.
+    ///
+    /// The size is aligned to the SDK requirements: the width is a multiple of 8,
+    /// and the height a multiple of 2.
+    pub fn max_roi_for_bin(&self, bin: u32) -> Result<(u32, u32), GenCamError> {
+        if !self.bins.contains(&(bin as u64)) {
+            return Err(GenCamError::GeneralError(format!(
+                "Binning {} not supported, supported: {:?}",
+                bin, self.bins
+            )));
+        }
+        let (width, height) = self.sensor_size;
+        let (width, height) = (width as u32 / bin, height as u32 / bin);
+        Ok((width & !7, height & !1))
+    }
+
+    pub fn get_roi(&self) -> &GenCamRoi {
+        &self.roi.0
+    }
+
+    /// Read the ROI from the SDK, bypassing the cached value.
+    pub fn roi_from_device(&self) -> Result<GenCamRoi, GenCamError> {
+        let handle = self.handle.handle();
+        let roi = AsiRoi::get(handle).map_err(|e| match e {
+            AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+            AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+            _ => GenCamError::GeneralError(e.to_string()),
+        })?;
+        Ok(roi.convert().0)
+    }
+
+    pub fn control_unit(&self, ctrl: &GenCamCtrl) -> Option<ControlUnit> {
+        self.sensor_ctrl
+            .get_controller(ctrl)
+            .or_else(|| self.device_ctrl.get_controller(ctrl))
+            .map(|(ctype, _)| ctype.unit())
+    }
+
+    pub fn get_concat_caps(&self) -> HashMap<GenCamCtrl, Property> {
+        let mut out = self.sensor_ctrl.list_properties().clone();
+        out.extend(self.device_ctrl.list_properties().clone());
+        out
+    }
+
+    pub fn get_info_handle(&self) -> GenCamInfoAsi {
+        let mut props = self.device_ctrl.list_properties().clone();
+        let exposure = GenCamCtrl::Exposure(ExposureCtrl::ExposureTime);
+        if let Some(prop) = self.sensor_ctrl.list_properties().get(&exposure) {
+            props.insert(exposure, prop.clone());
+        }
+        GenCamInfoAsi {
+            handle: self.handle.clone(),
+            serial: self.serial,
+            name: self.name,
+            has_cooler: self.has_cooler,
+            cooler_limit: self.cooler_limit.clone(),
+            capturing: self.capturing.clone(),
+            expstart: self.expstart.clone(),
+            info: self.info.clone(),
+            ctrl: self.device_ctrl.clone(),
+            props: Arc::new(props),
+        }
+    }
+
+    pub(crate) fn weak_handle(&self) -> Weak<AsiHandle> {
+        Arc::downgrade(&self.handle)
+    }
+
+    pub(crate) fn shared_handle(&self) -> Arc<AsiHandle> {
+        self.handle.clone()
+    }
+
+    pub fn sensor_model(&self) -> Option<&'static str> {
+        sensor_model(&self.info.name)
+    }
+
+    pub fn get_descriptor(&self) -> &GenCamDescriptor {
+        &self.info
+    }
+
+    /// Re-read the camera properties, and rebuild the descriptor.
+    pub fn refresh_info(&mut self) -> Result<&GenCamDescriptor, GenCamError> {
+        let handle = self.handle.handle();
+        let info = get_info(handle)?;
+        let usb3 = info.IsUSB3Camera == ASI_BOOL_ASI_TRUE as _;
+        let mut desc: GenCamDescriptor = info.into();
+        desc.info.insert(
+            "Serial Number".to_string(),
+            self.serial_number().to_string().into(),
+        );
+        if usb3 {
+            let uuid = get_uuid(handle).map_err(|e| match e {
+                AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+                AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+                _ => GenCamError::GeneralError(e.to_string()),
+            })?;
+            desc.info.insert("UUID".to_string(), uuid.into());
+        }
+        self.info = Arc::new(desc);
+        Ok(&self.info)
+    }
+}
+
+impl GenCamInfoAsi {
+    /// Get the serial number of the camera, as reported in the
+    /// `"Serial Number"` entry of the camera descriptor.
+    pub fn serial_number(&self) -> &str {
+        str::from_utf8(&self.serial)
+            .unwrap_or("")
+            .trim_end_matches(char::from(0))
+    }
+
+    /// Check whether an exposure is in progress, i.e. whether
+    /// [`cancel_capture`](GenCamInfo::cancel_capture) can succeed now.
+    ///
+    /// Useful to enable or disable a cancel button. The exposure may complete, or
+    /// be cancelled elsewhere, right after this returns.
+    pub fn can_cancel(&self) -> bool {
+        self.capturing.load(Ordering::SeqCst)
+    }
+
+    /// Get the time remaining in the current exposure.
+    ///
+    /// Returns `None` if no exposure is in progress, and zero once the exposure
+    /// time has elapsed but the image has not been downloaded yet.
+    pub fn remaining_exposure(&self) -> GenCamResult<Option<Duration>> {
+        if !self.capturing.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let Some(expstart) = self.expstart.load(Ordering::SeqCst) else {
+            return Ok(None);
+        };
+        // the exposure recorded at start, which a later change does not affect
+        let Some(exposure) = self
+            .last_exposure
+            .try_borrow()
+            .map_err(|_| access_violation("last_exposure"))?
+            .map(|info| info.exposure)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(exposure.saturating_sub(expstart.elapsed())))
+    }
+
+    /// Call `callback` once the sensor temperature has stayed within `tolerance` of
+    /// `target` (C) for `hold`.
+    ///
+    /// The temperature is polled on a background thread, which exits after the
+    /// callback fires, or without firing it if the camera is closed first. The
+    /// callback receives the last measured temperature.
+    pub fn on_temperature_stable<F>(
+        &self,
+        target: f32,
+        tolerance: f32,
+        hold: Duration,
+        callback: F,
+    ) -> GenCamResult<()>
+    where
+        F: FnOnce(f32) + Send + 'static,
+    {
+        if !self.has_cooler {
+            return Err(GenCamError::PropertyError {
+                control: DeviceCtrl::CoolerTemp.into(),
+                error: PropertyError::NotFound,
+            });
+        }
+        let handle = Arc::downgrade(&self.handle);
+        std::thread::spawn(move || {
+            let mut stable_since: Option<Instant> = None;
+            loop {
+                let Some(res) = handle
+                    .upgrade()
+                    .map(|handle| get_control_value(handle.handle(), AsiControlType::Temperature))
+                else {
+                    break;
+                };
+                let temp = match res {
+                    Ok((temp, _)) => Some(temp as f32 * 0.1),
+                    Err(GenCamError::CameraClosed | GenCamError::InvalidId(_)) => break,
+                    Err(e) => {
+                        warn!("Could not read temperature: {}", e);
+                        None
+                    }
+                };
+                match temp {
+                    Some(temp) if (temp - target).abs() <= tolerance => {
+                        if stable_since.get_or_insert_with(Instant::now).elapsed() >= hold {
+                            callback(temp);
+                            break;
+                        }
+                    }
+                    _ => stable_since = None,
+                }
+                sleep(TEMPERATURE_POLL_INTERVAL);
+            }
+        });
+        Ok(())
+    }
+
+    /// Limit the sustained cooler power to `percent` (1-100).
+    ///
+    /// While a limit below 100% is set, a background thread periodically checks
+    /// the cooler power, and raises the target temperature by 1 C whenever the
+    /// power exceeds the limit. Setting the limit to 100 stops the monitor; at most
+    /// one monitor runs at a time.
+    pub fn set_max_cooler_power(&self, percent: i64) -> GenCamResult<()> {
+        let control: GenCamCtrl = DeviceCtrl::CoolerPower.into();
+        if !self.has_cooler || !self.ctrl.contains(&control) {
+            return Err(GenCamError::PropertyError {
+                control,
+                error: PropertyError::NotFound,
+            });
+        }
+        if !(1..=COOLER_POWER_UNLIMITED).contains(&percent) {
+            return Err(GenCamError::PropertyError {
+                control,
+                error: PropertyError::ValueNotSupported,
+            });
+        }
+        let prev = self.cooler_limit.percent.swap(percent, Ordering::SeqCst);
+        if prev == COOLER_POWER_UNLIMITED && percent < COOLER_POWER_UNLIMITED {
+            // a monitor from an earlier limit may still be sleeping, retire it
+            let generation = self.cooler_limit.generation.fetch_add(1, Ordering::SeqCst) + 1;
+            let handle = Arc::downgrade(&self.handle);
+            let limit = self.cooler_limit.clone();
+            std::thread::spawn(move || {
+                cooler_monitor(handle, limit, generation, COOLER_MONITOR_INTERVAL)
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Target temperature to back off to, if the cooler `power` exceeds `limit`.
+fn cooler_backoff(power: i64, limit: i64, target: i64) -> Option<i64> {
+    (power > limit).then_some(target + COOLER_BACKOFF_STEP)
+}
+
+/// Raise the target temperature by one step if the cooler power exceeds `limit`.
+fn cooler_check(handle: i32, limit: i64) -> GenCamResult<()> {
+    let (power, _) = get_control_value(handle, AsiControlType::CoolerPowerPercent)?;
+    let (target, _) = get_control_value(handle, AsiControlType::TargetTemp)?;
+    if let Some(target) = cooler_backoff(power, limit, target) {
+        warn!(
+            "Cooler power {}% exceeds {}%, raising target temperature to {} C",
+            power, limit, target
+        );
+        set_control_value(
+            handle,
+            AsiControlType::TargetTemp,
+            target,
+            ASI_BOOL_ASI_FALSE as _,
+        )?;
+    }
+    Ok(())
+}
+
+/// Raise the target temperature while the cooler power exceeds `limit`, checking
+/// every `interval`.
+///
+/// Exits when the limit is lifted, a newer monitor is started, or the camera is closed.
+fn cooler_monitor(
+    handle: Weak<AsiHandle>,
+    limit: Arc<CoolerLimit>,
+    generation: u64,
+    interval: Duration,
+) {
+    loop {
+        sleep(interval);
+        if limit.generation.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        let max = limit.percent.load(Ordering::SeqCst);
+        if max >= COOLER_POWER_UNLIMITED {
+            break;
+        }
+        let Some(handle) = handle.upgrade() else {
+            break;
+        };
+        match cooler_check(handle.handle(), max) {
+            Err(GenCamError::CameraClosed | GenCamError::InvalidId(_)) => break,
+            Err(e) => warn!("Could not check cooler power: {}", e),
+            Ok(()) => {}
+        }
+    }
+}
+
+impl GenCamInfo for GenCamInfoAsi {
+    fn camera_ready(&self) -> bool {
+        true
+    }
+
+    fn camera_name(&self) -> &str {
+        str::from_utf8(&self.name)
+            .unwrap_or("")
             .trim_end_matches(char::from(0))
     }
 
@@ -993,7 +2988,7 @@ impl GenCamInfo for GenCamInfoAsi {
         let res = ASICALL!(ASIStopExposure(handle)).map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            _ => GenCamError::GeneralError(e.to_string()),
         });
         self.capturing.store(false, Ordering::SeqCst);
         res
@@ -1037,10 +3032,19 @@ impl GenCamInfo for GenCamInfoAsi {
     }
 
     fn list_properties(&self) -> &HashMap<GenCamCtrl, Property> {
-        self.ctrl.list_properties()
+        &self.props
     }
 
     fn get_property(&self, name: GenCamCtrl) -> GenCamResult<(PropertyValue, bool)> {
+        // exposure is a sensor control, but reading it does not disturb a capture
+        if name == GenCamCtrl::Exposure(ExposureCtrl::ExposureTime) {
+            let (exposure, auto) =
+                get_control_value(self.handle.handle(), AsiControlType::Exposure)?;
+            return Ok((
+                PropertyValue::from(Duration::from_micros(exposure as _)),
+                auto == ASI_BOOL_ASI_TRUE as _,
+            ));
+        }
         if !self.ctrl.contains(&name) {
             return Err(GenCamError::PropertyError {
                 control: name,
@@ -1065,3 +3069,2004 @@ impl GenCamInfo for GenCamInfoAsi {
         self.ctrl.set_value(&self.handle, &name, value, auto)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zwo_ffi::{
+        ASI_CONTROL_TYPE_ASI_ANTI_DEW_HEATER, ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD,
+        ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC, ASI_CONTROL_TYPE_ASI_EXPOSURE,
+        ASI_CONTROL_TYPE_ASI_FLIP, ASI_CONTROL_TYPE_ASI_GAIN, ASI_CONTROL_TYPE_ASI_OFFSET,
+        ASI_CONTROL_TYPE_ASI_TARGET_TEMP, ASI_CONTROL_TYPE_ASI_TEMPERATURE,
+        ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED, ASI_ERROR_CODE_ASI_ERROR_TIMEOUT,
+        ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE, ASI_EXPOSURE_STATUS_ASI_EXP_FAILED,
+        ASI_EXPOSURE_STATUS_ASI_EXP_IDLE, ASI_EXPOSURE_STATUS_ASI_EXP_SUCCESS,
+        ASI_EXPOSURE_STATUS_ASI_EXP_WORKING, ASI_IMG_TYPE_ASI_IMG_RGB24, ASI_IMG_TYPE_ASI_IMG_Y8,
+    };
+    use crate::zwo_ffi_wrapper::AsiErrorCode;
+    use crate::zwo_sdk::mock::{self, HEIGHT, WIDTH};
+    use refimage::GenericValue;
+
+    fn open_mock() -> AsiImager {
+        let dev = get_asi_devs(true)
+            .expect("Could not list cameras")
+            .remove(0);
+        open_device(&dev, None).expect("Could not open camera")
+    }
+
+    #[test]
+    fn open_reads_camera() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        assert_eq!(cam.camera_name(), "ZWO ASI120MM Mini");
+        assert_eq!(cam.serial_number(), "123456789ABCDEF0");
+        let full = GenCamRoi {
+            x_min: 0,
+            y_min: 0,
+            width: WIDTH as _,
+            height: HEIGHT as _,
+        };
+        assert_eq!(cam.get_roi(), &full);
+        // mono cameras are opened in RAW16
+        assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp16);
+        assert_eq!(sdk.camera().roi.3, ASI_IMG_TYPE_ASI_IMG_RAW16);
+        assert_eq!(sdk.calls("ASIInitCamera"), 1);
+        drop(cam);
+        assert!(!sdk.camera().open);
+    }
+
+    #[test]
+    fn open_invalid_id() {
+        let _sdk = mock::install();
+        let mut dev = get_asi_devs(true)
+            .expect("Could not list cameras")
+            .remove(0);
+        dev.id = 5;
+        let res = open_device(&dev, None);
+        assert!(matches!(res, Err(GenCamError::InvalidId(5))));
+    }
+
+    #[test]
+    fn set_roi_reads_back_window() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 32,
+            height: 16,
+        };
+        assert_eq!(cam.set_roi(&roi).expect("Could not set ROI"), &roi);
+        let state = sdk.camera();
+        assert_eq!(state.start, (8, 4));
+        assert_eq!(state.roi, (32, 16, 1, ASI_IMG_TYPE_ASI_IMG_RAW16));
+    }
+
+    #[test]
+    fn set_roi_outside_sensor() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let roi = GenCamRoi {
+            x_min: 40,
+            y_min: 0,
+            width: 32,
+            height: 16,
+        };
+        let calls = sdk.calls("ASISetROIFormat");
+        assert!(cam.set_roi(&roi).is_err());
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+    }
+
+    #[test]
+    fn capture_downloads_frame() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        cam.download_image().expect("Could not download image");
+        assert!(!cam.is_capturing());
+        let npix = (WIDTH * HEIGHT) as usize;
+        assert!(cam.imgstor[..npix]
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == i as u16));
+        let meta = cam.last_frame_metadata().expect("No frame metadata");
+        assert_eq!(meta.pixel_format, GenCamPixelBpp::Bpp16);
+        assert_eq!(meta.roi, *cam.get_roi());
+        assert!(matches!(
+            cam.download_image(),
+            Err(GenCamError::GeneralError(_))
+        ));
+    }
+
+    #[test]
+    fn start_exposure_maps_errors() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        sdk.fail("ASIStartExposure", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(
+            cam.start_exposure(),
+            Err(GenCamError::CameraClosed)
+        ));
+        assert!(!cam.is_capturing());
+        sdk.fail(
+            "ASIStartExposure",
+            ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE,
+        );
+        let err = cam
+            .start_exposure()
+            .expect_err("Exposure started in video mode");
+        match &err {
+            GenCamError::GeneralError(msg) => assert!(msg.contains("stop it")),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(
+            AsiErrorCode::from_error(&err),
+            Some(AsiErrorCode::VideoModeActive)
+        );
+        assert!(!cam.is_capturing());
+    }
+
+    #[test]
+    fn download_retries_timeouts() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        for _ in 0..DOWNLOAD_RETRY_ATTEMPTS {
+            sdk.fail("ASIGetDataAfterExp", ASI_ERROR_CODE_ASI_ERROR_TIMEOUT);
+        }
+        assert!(matches!(cam.download_image(), Err(GenCamError::TimedOut)));
+        assert_eq!(sdk.calls("ASIGetDataAfterExp"), DOWNLOAD_RETRY_ATTEMPTS);
+        assert!(!cam.is_capturing());
+    }
+
+    fn changed_settings(cam: &AsiImager) -> CameraSettings {
+        let mut settings = cam.export_settings().expect("Could not export settings");
+        settings.exposure = Duration::from_millis(250);
+        settings.gain = Some(150.0 * 0.1);
+        settings.offset = Some(30);
+        settings.bandwidth = Some(80);
+        settings.bin = 2;
+        settings.roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 16,
+            height: 8,
+        };
+        settings.pixel_format = GenCamPixelBpp::Bpp8;
+        settings.flip = (true, false);
+        settings.cooler_target = Some(-10);
+        settings
+    }
+
+    #[test]
+    fn import_settings_round_trip() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let settings = changed_settings(&cam);
+        cam.import_settings(&settings)
+            .expect("Could not import settings");
+        // the ROI is not rescaled by the binning
+        assert_eq!(
+            cam.export_settings().expect("Could not export settings"),
+            settings
+        );
+        let state = sdk.camera();
+        assert_eq!(state.value(ASI_CONTROL_TYPE_ASI_OFFSET), Some(30));
+        assert_eq!(
+            state.value(ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD),
+            Some(80)
+        );
+        assert_eq!(state.roi, (16, 8, 2, ASI_IMG_TYPE_ASI_IMG_RAW8));
+    }
+
+    #[test]
+    fn import_settings_rolls_back() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        let before = cam.export_settings().expect("Could not export settings");
+        let mut settings = changed_settings(&cam);
+        settings.offset = Some(200); // outside 0..=80
+        assert!(matches!(
+            cam.import_settings(&settings),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::ValueNotSupported,
+                ..
+            })
+        ));
+        assert_eq!(
+            cam.export_settings().expect("Could not export settings"),
+            before
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn camera_settings_serde_round_trip() {
+        let _sdk = mock::install();
+        let cam = open_mock();
+        let settings = changed_settings(&cam);
+        let json = serde_json::to_string(&settings).expect("Could not serialize settings");
+        let back: CameraSettings =
+            serde_json::from_str(&json).expect("Could not deserialize settings");
+        assert_eq!(back, settings);
+    }
+
+    #[test]
+    fn cooler_backoff_steps() {
+        assert_eq!(cooler_backoff(80, 80, -10), None);
+        assert_eq!(cooler_backoff(81, 80, -10), Some(-9));
+        assert_eq!(cooler_backoff(0, 1, 0), None);
+        assert_eq!(cooler_backoff(100, 1, 0), Some(1));
+    }
+
+    #[test]
+    fn cooler_limit_restarts_single_monitor() {
+        let _sdk = mock::install();
+        let cam = open_mock();
+        let info = cam.get_info_handle();
+        assert!(info.set_max_cooler_power(0).is_err());
+        assert!(info.set_max_cooler_power(101).is_err());
+        info.set_max_cooler_power(50).unwrap();
+        // changing a limit that is already set does not start another monitor
+        info.set_max_cooler_power(60).unwrap();
+        assert_eq!(cam.cooler_limit.generation.load(Ordering::SeqCst), 1);
+        info.set_max_cooler_power(COOLER_POWER_UNLIMITED).unwrap();
+        info.set_max_cooler_power(50).unwrap();
+        assert_eq!(cam.cooler_limit.generation.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn cooler_monitor_exits_when_superseded() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        cam.cooler_limit.percent.store(50, Ordering::SeqCst);
+        cam.cooler_limit.generation.store(2, Ordering::SeqCst);
+        let calls = sdk.calls("ASIGetControlValue");
+        cooler_monitor(
+            cam.weak_handle(),
+            cam.cooler_limit.clone(),
+            1,
+            Duration::ZERO,
+        );
+        assert_eq!(sdk.calls("ASIGetControlValue"), calls);
+    }
+
+    #[test]
+    fn cooler_monitor_raises_target() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let handle = cam.handle.handle();
+        set_control_value(
+            handle,
+            AsiControlType::CoolerPowerPercent,
+            90,
+            ASI_BOOL_ASI_FALSE as _,
+        )
+        .unwrap();
+        set_control_value(
+            handle,
+            AsiControlType::TargetTemp,
+            -10,
+            ASI_BOOL_ASI_FALSE as _,
+        )
+        .unwrap();
+        cam.cooler_limit.percent.store(50, Ordering::SeqCst);
+        let (weak, limit) = (cam.weak_handle(), cam.cooler_limit.clone());
+        let monitor =
+            std::thread::spawn(move || cooler_monitor(weak, limit, 0, Duration::from_millis(1)));
+        let start = Instant::now();
+        while sdk.camera().value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP) == Some(-10) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Target was not raised"
+            );
+            sleep(Duration::from_millis(1));
+        }
+        // lifting the limit stops the monitor
+        cam.cooler_limit
+            .percent
+            .store(COOLER_POWER_UNLIMITED, Ordering::SeqCst);
+        monitor.join().unwrap();
+        assert!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP) > Some(-10));
+    }
+
+    #[test]
+    fn percent_to_raw_rounds() {
+        assert_eq!(percent_to_raw(0.0, 50, 160), 50);
+        assert_eq!(percent_to_raw(50.0, 50, 160), 105);
+        assert_eq!(percent_to_raw(100.0, 50, 160), 160);
+        assert_eq!(percent_to_raw(33.3, 0, 10), 3);
+        // halves round away from zero
+        assert_eq!(percent_to_raw(35.0, 0, 10), 4);
+        assert_eq!(percent_to_raw(34.9, 0, 10), 3);
+    }
+
+    #[test]
+    fn percent_to_raw_clamps() {
+        assert_eq!(percent_to_raw(-10.0, 50, 160), 50);
+        assert_eq!(percent_to_raw(150.0, 50, 160), 160);
+        assert_eq!(percent_to_raw(50.0, 100, 100), 100);
+        assert_eq!(percent_to_raw(50.0, 100, 0), 100);
+    }
+
+    #[test]
+    fn raw_to_percent_clamps() {
+        assert_eq!(raw_to_percent(105, 50, 160), 50.0);
+        assert_eq!(raw_to_percent(10, 50, 160), 0.0);
+        assert_eq!(raw_to_percent(200, 50, 160), 100.0);
+        assert_eq!(raw_to_percent(100, 100, 100), 0.0);
+        // round trip through the raw range
+        for raw in 50..=160 {
+            assert_eq!(percent_to_raw(raw_to_percent(raw, 50, 160), 50, 160), raw);
+        }
+    }
+
+    #[test]
+    fn frame_stats_empty() {
+        assert_eq!(frame_stats::<u16>(&[], u16::MAX), None);
+        assert_eq!(frame_stats::<u8>(&[], u8::MAX as _), None);
+    }
+
+    #[test]
+    fn frame_stats_values() {
+        let stats = frame_stats(&[2u16, 4, 4, 4, 5, 5, 7, 9], u16::MAX).unwrap();
+        assert_eq!((stats.min, stats.max), (2, 9));
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.stddev, 2.0);
+        assert_eq!(stats.saturated, 0.0);
+        let stats = frame_stats(&[7u8; 16], u8::MAX as _).unwrap();
+        assert_eq!((stats.min, stats.max, stats.mean), (7, 7, 7.0));
+        assert_eq!(stats.stddev, 0.0);
+    }
+
+    #[test]
+    fn frame_stats_saturated_fraction() {
+        // 12-bit sensor, left-shifted: saturates at 0xfff0
+        let data = [0u16, 0xffef, 0xfff0, 0xffff];
+        let stats = frame_stats(&data, 0xfff0).unwrap();
+        assert_eq!(stats.saturated, 0.5);
+        let stats = frame_stats(&[u8::MAX, 254, 0, u8::MAX], u8::MAX as _).unwrap();
+        assert_eq!(stats.saturated, 0.5);
+        let stats = frame_stats(&[u8::MAX; 4], u8::MAX as _).unwrap();
+        assert_eq!(stats.saturated, 1.0);
+    }
+
+    fn gradient(width: usize, height: usize) -> Vec<u16> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x + y) as u16))
+            .collect()
+    }
+
+    #[test]
+    fn corner_stats_gradient() {
+        let stats = corner_stats(&gradient(16, 16), 16, 16, (2, 2)).unwrap();
+        assert_eq!(stats.top_left, 1.0);
+        assert_eq!(stats.top_right, 15.0);
+        assert_eq!(stats.bottom_left, 15.0);
+        assert_eq!(stats.bottom_right, 29.0);
+        assert_eq!(stats.center, 15.0);
+    }
+
+    #[test]
+    fn corner_stats_clamps_window() {
+        let data = gradient(16, 16);
+        let clamped = corner_stats(&data, 16, 16, (100, 100)).unwrap();
+        assert_eq!(clamped, corner_stats(&data, 16, 16, (8, 8)).unwrap());
+        assert_eq!(clamped.top_left, 7.0);
+        assert_eq!(clamped.bottom_right, 23.0);
+        // regions are at least one pixel
+        let single = corner_stats(&data, 16, 16, (0, 0)).unwrap();
+        assert_eq!((single.top_left, single.bottom_right), (0.0, 30.0));
+    }
+
+    #[test]
+    fn corner_stats_rejects_short_frame() {
+        assert_eq!(corner_stats::<u16>(&[], 0, 0, (1, 1)), None);
+        assert_eq!(corner_stats(&gradient(16, 15), 16, 16, (2, 2)), None);
+    }
+
+    /// Mean (fraction of full scale) of a flat frame from a light source that
+    /// fills 10% of the well per 10 ms.
+    fn flat_fraction(exposure: Duration) -> f64 {
+        (exposure.as_secs_f64() * 10.0).min(1.0)
+    }
+
+    fn converge(mut exposure: Duration, target: f64) -> Option<Duration> {
+        let (min, max) = (Duration::from_micros(32), Duration::from_secs(10));
+        for _ in 0..FLAT_MAX_ITERATIONS {
+            let fraction = flat_fraction(exposure);
+            if (fraction - target).abs() <= FLAT_TOLERANCE * target {
+                return Some(exposure);
+            }
+            exposure = next_flat_exposure(exposure, fraction, target, min, max);
+        }
+        None
+    }
+
+    #[test]
+    fn flat_exposure_converges() {
+        // from underexposed, in steps of at most FLAT_MAX_STEP
+        let exposure = converge(Duration::from_millis(1), 0.5).expect("Did not converge");
+        assert!((flat_fraction(exposure) - 0.5).abs() <= FLAT_TOLERANCE * 0.5);
+        // from saturated, halving the exposure each time
+        let exposure = converge(Duration::from_secs(1), 0.5).expect("Did not converge");
+        assert!((flat_fraction(exposure) - 0.5).abs() <= FLAT_TOLERANCE * 0.5);
+    }
+
+    #[test]
+    fn flat_exposure_clamped() {
+        let (min, max) = (Duration::from_millis(1), Duration::from_secs(2));
+        assert_eq!(
+            next_flat_exposure(Duration::from_secs(1), 0.1, 0.5, min, max),
+            max
+        );
+        assert_eq!(
+            next_flat_exposure(Duration::from_millis(2), 1.0, 0.1, min, max),
+            min
+        );
+        // a single step scales the exposure by at most FLAT_MAX_STEP
+        let next = next_flat_exposure(Duration::from_millis(10), 0.001, 0.5, min, max);
+        assert!((next.as_secs_f64() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flat_exposure_zero_mean() {
+        let (min, max) = (Duration::from_millis(1), Duration::from_secs(2));
+        let next = next_flat_exposure(Duration::from_millis(10), 0.0, 0.5, min, max);
+        assert!((next.as_secs_f64() - 0.1).abs() < 1e-9);
+        assert_eq!(
+            next_flat_exposure(Duration::from_secs(1), 0.0, 0.5, min, max),
+            max
+        );
+    }
+
+    #[test]
+    fn capture_averaged_keeps_fraction() {
+        static PIXELS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let npix = (WIDTH * HEIGHT) as usize;
+        // alternate frames of 0 and 1
+        PIXELS.store(0, Ordering::SeqCst);
+        sdk.camera().pixel =
+            |_| ((PIXELS.fetch_add(1, Ordering::SeqCst) / (WIDTH * HEIGHT) as usize) % 2) as u16;
+        let img = cam.capture_averaged(2).expect("Could not capture frames");
+        let DynamicImageOwned::F32(mean) = img.get_image() else {
+            panic!("Average is not a floating point image");
+        };
+        assert_eq!(mean.as_slice().len(), npix);
+        assert!(mean.as_slice().iter().all(|&px| px == 0.5));
+        assert_eq!(sdk.calls("ASIStartExposure"), 2);
+        assert!(matches!(
+            cam.capture_averaged(0),
+            Err(GenCamError::GeneralError(_))
+        ));
+    }
+
+    #[test]
+    fn download_luma_mono_unchanged() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        let img = cam.download_luma().expect("Could not download image");
+        assert!(img.color_space() == ColorSpace::Gray);
+        let DynamicImageOwned::U16(img) = img.get_image() else {
+            panic!("Pixel type changed");
+        };
+        assert!(img
+            .as_slice()
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == i as u16));
+    }
+
+    #[test]
+    fn download_luma_debayers() {
+        let sdk = mock::install();
+        {
+            let mut camera = sdk.camera();
+            camera.info.IsColorCam = ASI_BOOL_ASI_TRUE;
+            camera.info.BayerPattern = ASI_BAYER_PATTERN_ASI_BAYER_RG;
+        }
+        let mut cam = open_mock();
+        assert!(cam.is_color());
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        let img = cam.download_luma().expect("Could not download image");
+        assert_eq!(img.channels(), 1);
+        assert!(img.color_space() == ColorSpace::Gray);
+        let roi = cam.get_roi();
+        assert_eq!(
+            (img.width(), img.height()),
+            (roi.width as usize, roi.height as usize)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn frame_metadata_json_round_trip() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        assert_eq!(cam.last_frame_metadata_json(), None);
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        cam.download_image().expect("Could not download image");
+        let json = cam
+            .last_frame_metadata_json()
+            .expect("Could not serialize metadata");
+        let back: FrameMetadata =
+            serde_json::from_str(&json).expect("Could not deserialize metadata");
+        assert_eq!(Some(&back), cam.last_frame_metadata());
+    }
+
+    #[test]
+    fn set_exposure_micros_checks_range() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let calls = sdk.calls("ASISetControlValue");
+        for exposure in [0, 31, 2_000_000_001] {
+            assert!(matches!(
+                cam.set_exposure_micros(exposure),
+                Err(GenCamError::PropertyError {
+                    error: PropertyError::ValueNotSupported,
+                    ..
+                })
+            ));
+        }
+        assert_eq!(sdk.calls("ASISetControlValue"), calls);
+        cam.set_exposure_micros(32).expect("Could not set exposure");
+        assert_eq!(cam.exposure_micros(), 32);
+        cam.set_exposure_micros(2_000_000_000)
+            .expect("Could not set exposure");
+        assert_eq!(cam.exposure_micros(), 2_000_000_000);
+    }
+
+    #[test]
+    fn set_exposure_fast_checks_range() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let calls = sdk.calls("ASISetControlValue");
+        for exposure in [Duration::ZERO, Duration::from_secs(2001)] {
+            assert!(matches!(
+                cam.set_exposure_fast(exposure, false),
+                Err(GenCamError::PropertyError {
+                    error: PropertyError::ValueNotSupported,
+                    ..
+                })
+            ));
+        }
+        assert_eq!(sdk.calls("ASISetControlValue"), calls);
+        cam.set_exposure_fast(Duration::from_millis(5), false)
+            .expect("Could not set exposure");
+        assert_eq!(cam.exposure_micros(), 5000);
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE),
+            Some(5000)
+        );
+    }
+
+    #[test]
+    fn default_offset_from_cached_caps() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let calls = sdk.calls("ASIGetControlCaps");
+        assert_eq!(cam.default_offset(), Some(10));
+        // no SDK call is made
+        assert_eq!(sdk.calls("ASIGetControlCaps"), calls);
+    }
+
+    #[test]
+    fn restore_full_roi_on_close() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 32,
+            height: 16,
+        };
+        cam.set_roi(&roi).expect("Could not set ROI");
+        cam.restore_full_roi_on_drop(true);
+        // the info handle keeps the camera open
+        let info = cam.get_info_handle();
+        let calls = sdk.calls("ASISetROIFormat");
+        drop(cam);
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+        drop(info);
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls + 1);
+        let state = sdk.camera();
+        assert_eq!(state.roi, (WIDTH, HEIGHT, 1, ASI_IMG_TYPE_ASI_IMG_RAW16));
+        assert_eq!(state.start, (0, 0));
+        assert!(!state.open);
+    }
+
+    #[test]
+    fn keep_roi_on_close() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 32,
+            height: 16,
+        };
+        cam.set_roi(&roi).expect("Could not set ROI");
+        let calls = sdk.calls("ASISetROIFormat");
+        drop(cam);
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+        assert_eq!(sdk.camera().roi, (32, 16, 1, ASI_IMG_TYPE_ASI_IMG_RAW16));
+    }
+
+    /// Wait for `n` calls to `func`, for at most 5 s.
+    fn wait_calls(sdk: &mock::MockGuard, func: &str, n: usize) {
+        let start = Instant::now();
+        while sdk.calls(func) < n {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} was not called",
+                func
+            );
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn pulse_guide_checks_duration() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.set_max_pulse_duration(Duration::from_secs(1));
+        for duration in [Duration::ZERO, Duration::from_millis(1001)] {
+            assert!(matches!(
+                cam.pulse_guide(GuideDir::North, duration),
+                Err(GenCamError::PropertyError {
+                    error: PropertyError::ValueNotSupported,
+                    ..
+                })
+            ));
+        }
+        assert_eq!(sdk.calls("ASIPulseGuideOn"), 0);
+        drop(cam);
+        sdk.camera().info.ST4Port = ASI_BOOL_ASI_FALSE;
+        let cam = open_mock();
+        assert!(matches!(
+            cam.pulse_guide(GuideDir::North, Duration::from_millis(1)),
+            Err(GenCamError::InvalidControlType(_))
+        ));
+    }
+
+    #[test]
+    fn pulse_guide_schedules_off() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        cam.pulse_guide(GuideDir::East, Duration::from_millis(1))
+            .expect("Could not start pulse");
+        assert_eq!(sdk.calls("ASIPulseGuideOn"), 1);
+        wait_calls(&sdk, "ASIPulseGuideOff", 1);
+    }
+
+    #[test]
+    fn pulse_guide_not_cut_short() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let duration = Duration::from_millis(50);
+        cam.pulse_guide(GuideDir::West, duration)
+            .expect("Could not start pulse");
+        cam.pulse_guide(GuideDir::West, duration)
+            .expect("Could not start pulse");
+        wait_calls(&sdk, "ASIPulseGuideOff", 1);
+        // the first pulse does not end the second one
+        sleep(duration * 2);
+        assert_eq!(sdk.calls("ASIPulseGuideOff"), 1);
+    }
+
+    #[test]
+    fn pulse_guide_skips_closed_camera() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let duration = Duration::from_millis(20);
+        cam.pulse_guide(GuideDir::South, duration)
+            .expect("Could not start pulse");
+        drop(cam);
+        sleep(duration * 3);
+        assert_eq!(sdk.calls("ASIPulseGuideOff"), 0);
+    }
+
+    #[test]
+    fn access_violation_names_field() {
+        let _sdk = mock::install();
+        let cam = open_mock();
+        let held = cam.gain.borrow();
+        match cam.get_gain() {
+            Err(GenCamError::GeneralError(msg)) => {
+                assert_eq!(msg, "AccessViolation: could not borrow `gain`")
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        drop(held);
+        assert_eq!(cam.get_gain().expect("Could not get gain"), 100);
+    }
+
+    #[cfg(feature = "shmem")]
+    #[test]
+    fn downloaded_frames_go_to_shmem() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        let name = format!("asi-test-camera-ring-{}", std::process::id());
+        cam.stream_to_shmem(&name, 2)
+            .expect("Could not create ring");
+        // replacing the ring keeps it
+        cam.stream_to_shmem(&name, 2)
+            .expect("Could not create ring");
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 32,
+            height: 16,
+        };
+        cam.set_roi(&roi).expect("Could not set ROI");
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        cam.download_image().expect("Could not download image");
+        let reader = ShmemRing::open(&name).expect("Could not open ring");
+        let frame = reader.latest().expect("No frame in the ring");
+        assert_eq!(frame.seq, 1);
+        assert_eq!(
+            (frame.width, frame.height, frame.bytes_per_pixel),
+            (32, 16, 2)
+        );
+        let expected: Vec<u8> = (0..32 * 16u16).flat_map(u16::to_ne_bytes).collect();
+        assert_eq!(frame.data, expected);
+        cam.stop_shmem_stream();
+        assert!(ShmemRing::open(&name).is_err());
+    }
+
+    #[test]
+    fn abort_and_drain_stops_exposure() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        sdk.camera().outcome = ASI_EXPOSURE_STATUS_ASI_EXP_WORKING;
+        cam.start_exposure().expect("Could not start exposure");
+        cam.abort_and_drain().expect("Could not abort exposure");
+        assert_eq!(sdk.camera().status, ASI_EXPOSURE_STATUS_ASI_EXP_FAILED);
+        assert_eq!(sdk.calls("ASIGetDataAfterExp"), 0);
+        assert!(matches!(cam.get_state(), Ok(GenCamState::Idle)));
+        assert!(cam.last_exposure.borrow().is_none());
+        assert!(matches!(
+            cam.download_image(),
+            Err(GenCamError::ExposureNotStarted)
+        ));
+    }
+
+    #[test]
+    fn abort_and_drain_discards_frame() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        cam.abort_and_drain().expect("Could not abort exposure");
+        // the completed frame was read out, and is not pending anymore
+        assert_eq!(sdk.calls("ASIGetDataAfterExp"), 1);
+        assert_eq!(sdk.camera().status, ASI_EXPOSURE_STATUS_ASI_EXP_IDLE);
+        assert!(matches!(cam.get_state(), Ok(GenCamState::Idle)));
+        assert!(cam.last_exposure.borrow().is_none());
+        assert!(matches!(
+            cam.download_image(),
+            Err(GenCamError::ExposureNotStarted)
+        ));
+        // the next capture starts clean
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(cam.image_ready().expect("Could not check exposure"));
+        cam.download_image().expect("Could not download image");
+    }
+
+    #[test]
+    fn remaining_exposure_from_start() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        assert_eq!(cam.remaining_exposure().expect("No exposure state"), None);
+        cam.set_exposure_micros(2_000_000)
+            .expect("Could not set exposure");
+        sdk.camera().outcome = ASI_EXPOSURE_STATUS_ASI_EXP_WORKING;
+        cam.start_exposure().expect("Could not start exposure");
+        // changing the exposure does not affect the exposure in progress
+        sdk.camera()
+            .controls
+            .iter_mut()
+            .filter(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_EXPOSURE)
+            .for_each(|(_, value, _)| *value = 100);
+        let calls = sdk.calls("ASIGetControlValue");
+        let remaining = cam
+            .remaining_exposure()
+            .expect("No exposure state")
+            .expect("No exposure in progress");
+        assert!(remaining <= Duration::from_secs(2));
+        assert!(remaining > Duration::from_secs(1));
+        assert_eq!(sdk.calls("ASIGetControlValue"), calls);
+        cam.abort_and_drain().expect("Could not abort exposure");
+        assert_eq!(cam.remaining_exposure().expect("No exposure state"), None);
+    }
+
+    #[test]
+    fn set_uuid_checks_length() {
+        let sdk = mock::install();
+        sdk.camera().info.IsUSB3Camera = ASI_BOOL_ASI_TRUE;
+        let mut cam = open_mock();
+        for uuid in ["", "SHORT", "TOO-LONG-"] {
+            assert!(matches!(
+                cam.set_property(&uuid_ctrl(), &PropertyValue::EnumStr(uuid.into()), false),
+                Err(GenCamError::PropertyError {
+                    error: PropertyError::ValueNotSupported,
+                    ..
+                })
+            ));
+        }
+        assert!(matches!(
+            cam.set_property(&uuid_ctrl(), &PropertyValue::Int(8), false),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::ValueNotSupported,
+                ..
+            })
+        ));
+        assert_eq!(sdk.calls("ASISetID"), 0);
+        cam.set_property(
+            &uuid_ctrl(),
+            &PropertyValue::EnumStr("ASI-0001".into()),
+            false,
+        )
+        .expect("Could not set UUID");
+        assert_eq!(sdk.calls("ASISetID"), 1);
+        assert_eq!(&sdk.camera().uuid, b"ASI-0001");
+    }
+
+    #[test]
+    fn set_uuid_needs_usb3() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        assert!(matches!(
+            cam.set_property(
+                &uuid_ctrl(),
+                &PropertyValue::EnumStr("ASI-0001".into()),
+                false
+            ),
+            Err(GenCamError::InvalidControlType(_))
+        ));
+        assert_eq!(sdk.calls("ASISetID"), 0);
+    }
+
+    #[test]
+    fn set_roi_outside_binned_sensor() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.set_binning(2).expect("Could not set binning");
+        // fits the unbinned sensor, but not the 32x24 binned one
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 0,
+            width: 32,
+            height: 16,
+        };
+        let calls = sdk.calls("ASISetROIFormat");
+        match cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp16) {
+            Err(GenCamError::PropertyError { control, error }) => {
+                assert_eq!(control, roi_ctrl());
+                assert!(matches!(error, PropertyError::ValueNotSupported));
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+    }
+
+    #[test]
+    fn set_binning_keeps_minimum_roi() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.set_centered_roi(8, 2, 1).expect("Could not set ROI");
+        // halving an 8x2 window would give an empty one
+        cam.set_binning(2).expect("Could not set binning");
+        let roi = cam.get_roi();
+        assert_eq!((roi.width, roi.height), (8, 2));
+        let (width, height, bin, _) = sdk.camera().roi;
+        assert_eq!((width, height, bin), (8, 2, 2));
+    }
+
+    #[test]
+    fn set_property_rejects_before_cooler_on() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let calls = sdk.calls("ASISetControlValue");
+        match cam.set_property(
+            &DeviceCtrl::Temperature.into(),
+            &PropertyValue::Float(-10.0),
+            false,
+        ) {
+            Err(GenCamError::PropertyError { error, .. }) => {
+                assert!(matches!(error, PropertyError::ReadOnly))
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // out of the -40..30 target range
+        match cam.set_property(
+            &DeviceCtrl::CoolerTemp.into(),
+            &PropertyValue::Int(100),
+            false,
+        ) {
+            Err(GenCamError::PropertyError { control, .. }) => {
+                assert_eq!(control, DeviceCtrl::CoolerTemp.into())
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // the cooler was not turned on
+        assert_eq!(sdk.calls("ASISetControlValue"), calls);
+    }
+
+    #[test]
+    fn temperature_stable_fires_once() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let info = cam.get_info_handle();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let polls = sdk.calls("ASIGetControlValue");
+        let temps = fired.clone();
+        info.on_temperature_stable(-10.0, 0.5, Duration::ZERO, move |temp| {
+            temps.lock().unwrap().push(temp)
+        })
+        .expect("Could not watch the temperature");
+        // the sensor is at 20 C
+        wait_calls(&sdk, "ASIGetControlValue", polls + 1);
+        assert!(fired.lock().unwrap().is_empty());
+        sdk.camera()
+            .set_value(ASI_CONTROL_TYPE_ASI_TEMPERATURE, -100);
+        let start = Instant::now();
+        while fired.lock().unwrap().is_empty() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Callback did not fire"
+            );
+            sleep(Duration::from_millis(1));
+        }
+        // the watcher stops after firing
+        let polls = sdk.calls("ASIGetControlValue");
+        sleep(TEMPERATURE_POLL_INTERVAL * 2);
+        assert_eq!(sdk.calls("ASIGetControlValue"), polls);
+        assert_eq!(*fired.lock().unwrap(), vec![-10.0]);
+    }
+
+    #[test]
+    fn info_handle_lists_exposure() {
+        let _sdk = mock::install();
+        let cam = open_mock();
+        cam.set_exposure_micros(5_000)
+            .expect("Could not set exposure");
+        let mut info = cam.get_info_handle();
+        let exposure = GenCamCtrl::Exposure(ExposureCtrl::ExposureTime);
+        assert!(info.list_properties().contains_key(&exposure));
+        assert!(info
+            .list_properties()
+            .contains_key(&DeviceCtrl::Temperature.into()));
+        let (value, _) = info
+            .get_property(exposure)
+            .expect("Could not read exposure");
+        assert_eq!(value, PropertyValue::from(Duration::from_millis(5)));
+        // listed, but only the camera can set it
+        assert!(matches!(
+            info.set_property(exposure, &value, false),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::NotFound,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn anti_dew_heater_round_trip() {
+        let sdk = mock::install();
+        sdk.camera().controls.push(mock::control(
+            ASI_CONTROL_TYPE_ASI_ANTI_DEW_HEATER,
+            "AntiDewHeater",
+            0,
+            1,
+            0,
+            false,
+        ));
+        let mut cam = open_mock();
+        let heater: GenCamCtrl = DeviceCtrl::Custom("AntiDewHeater".into()).into();
+        assert!(cam.get_concat_caps().contains_key(&heater));
+        cam.set_property(&heater, &PropertyValue::Int(1), false)
+            .expect("Could not turn the heater on");
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_ANTI_DEW_HEATER),
+            Some(1)
+        );
+        let (value, auto) = cam
+            .get_property(&heater)
+            .expect("Could not read the heater");
+        assert_eq!(value, PropertyValue::Int(1));
+        assert!(!auto);
+    }
+
+    #[test]
+    fn download_same_frame_twice() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        let calls = sdk.calls("ASIGetDataAfterExp");
+        match cam.download_image() {
+            Err(GenCamError::GeneralError(msg)) => assert_eq!(msg, "Frame already downloaded"),
+            res => panic!("Unexpected result: {:?}", res.map(|_| ())),
+        }
+        assert_eq!(sdk.calls("ASIGetDataAfterExp"), calls);
+        // a new exposure can be downloaded again
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert_eq!(sdk.calls("ASIGetDataAfterExp"), calls + 1);
+    }
+
+    #[test]
+    fn download_right_aligns_12_bit() {
+        let sdk = mock::install();
+        // the SDK shifts the 12-bit samples to fill 16 bits
+        sdk.camera().pixel = |i| ((i as u16) & 0xfff) << 4;
+        let mut cam = open_mock();
+        assert_eq!(cam.bitdepth, 12);
+        let npix = (WIDTH * HEIGHT) as usize;
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert!(cam.imgstor[..npix]
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == ((i as u16) & 0xfff) << 4));
+        cam.set_data_alignment(DataAlignment::RightAligned);
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert!(cam.imgstor[..npix]
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == (i as u16) & 0xfff));
+    }
+
+    /// Get the value of the metadata entry `key` of a downloaded frame.
+    fn key(img: &GenericImageRef, key: &str) -> GenericValue {
+        img.get_key(key)
+            .unwrap_or_else(|| panic!("No {} entry", key))
+            .get_value()
+            .clone()
+    }
+
+    #[test]
+    fn download_records_binning() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        cam.set_binning(2).expect("Could not set binning");
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam.download_image().expect("Could not download image");
+        assert_eq!(key(&img, "XBINNING"), GenericValue::from(2i32));
+        assert_eq!(key(&img, "YBINNING"), GenericValue::from(2i32));
+        assert_eq!(cam.last_frame_metadata().expect("No frame metadata").bin, 2);
+    }
+
+    #[test]
+    fn download_records_flip() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        cam.set_flip(true, false).expect("Could not flip");
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_FLIP),
+            Some(ASI_FLIP_STATUS_ASI_FLIP_HORIZ as _)
+        );
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam.download_image().expect("Could not download image");
+        assert_eq!(key(&img, "FLIPX"), GenericValue::from(true));
+        assert_eq!(key(&img, "FLIPY"), GenericValue::from(false));
+    }
+
+    #[test]
+    fn download_uses_timestamp_source() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        assert_eq!(cam.timestamp_source(), TimestampSource::SystemTime);
+        // a reference far from the wall clock tells the sources apart
+        cam.epoch = (Instant::now(), SystemTime::UNIX_EPOCH);
+        cam.set_timestamp_source(TimestampSource::Monotonic);
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        let tstamp = cam
+            .last_frame_metadata()
+            .expect("No frame metadata")
+            .timestamp;
+        let since_epoch = tstamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Timestamp before the reference");
+        assert!(since_epoch < Duration::from_secs(60));
+        cam.set_timestamp_source(TimestampSource::SystemTime);
+        let before = SystemTime::now();
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        let tstamp = cam
+            .last_frame_metadata()
+            .expect("No frame metadata")
+            .timestamp;
+        assert!(tstamp >= before && tstamp <= SystemTime::now());
+    }
+
+    #[test]
+    fn open_defaults_mono_to_raw16() {
+        {
+            let sdk = mock::install();
+            let cam = open_mock();
+            assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp16);
+            assert_eq!(sdk.camera().roi.3, ASI_IMG_TYPE_ASI_IMG_RAW16);
+        }
+        {
+            // mono, without 16-bit support
+            let sdk = mock::install();
+            sdk.camera().info.SupportedVideoFormat[1] = ASI_IMG_TYPE_ASI_IMG_END;
+            let cam = open_mock();
+            assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+            assert_eq!(sdk.camera().roi.3, ASI_IMG_TYPE_ASI_IMG_RAW8);
+        }
+        {
+            let sdk = mock::install();
+            sdk.camera().info.IsColorCam = ASI_BOOL_ASI_TRUE;
+            let cam = open_mock();
+            assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+            assert_eq!(sdk.camera().roi.3, ASI_IMG_TYPE_ASI_IMG_RAW8);
+        }
+    }
+
+    #[test]
+    fn uuid_round_trip() {
+        let sdk = mock::install();
+        sdk.camera().info.IsUSB3Camera = ASI_BOOL_ASI_TRUE;
+        let mut cam = open_mock();
+        let (uuid, _) = cam.get_property(&uuid_ctrl()).expect("Could not read UUID");
+        assert_eq!(uuid, PropertyValue::EnumStr("MOCKUUID".into()));
+        let uuid = PropertyValue::EnumStr("ASI-0001".into());
+        cam.set_property(&uuid_ctrl(), &uuid, false)
+            .expect("Could not set UUID");
+        let calls = sdk.calls("ASIGetID");
+        let (read, _) = cam.get_property(&uuid_ctrl()).expect("Could not read UUID");
+        assert_eq!(read, uuid);
+        assert_eq!(sdk.calls("ASIGetID"), calls + 1);
+        // the info handle reads it too
+        let info = cam.get_info_handle();
+        let (read, _) = info.get_property(uuid_ctrl()).expect("Could not read UUID");
+        assert_eq!(read, uuid);
+    }
+
+    #[test]
+    fn dynamic_range_from_bit_depth_and_gain() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        // 4 e-/ADU: the noise floor is one ADU, so the range is the bit depth
+        assert_eq!(cam.dynamic_range_stops(), Some(12.0));
+        // 0.25 e-/ADU: the noise floor is one electron, 4 ADU
+        sdk.camera().info.ElecPerADU = 0.25;
+        cam.set_gain(200).expect("Could not set gain");
+        assert_eq!(cam.dynamic_range_stops(), Some(10.0));
+        sdk.camera().info.ElecPerADU = 0.0;
+        cam.set_gain(300).expect("Could not set gain");
+        assert_eq!(cam.dynamic_range_stops(), None);
+    }
+
+    #[test]
+    fn max_fps_from_link_and_frame_size() {
+        let fps = |usb3: bool| {
+            let sdk = mock::install();
+            {
+                let mut camera = sdk.camera();
+                camera.info.IsUSB3Camera = usb3 as _;
+                camera.info.IsUSB3Host = usb3 as _;
+            }
+            let mut cam = open_mock();
+            // 16-bit full frame, at the 50% default bandwidth
+            let full = cam.estimated_max_fps();
+            sdk.camera()
+                .set_value(ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD, 100);
+            assert!((cam.estimated_max_fps() / full - 2.0).abs() < 1e-4);
+            let roi = *cam.get_roi();
+            cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp8)
+                .expect("Could not set format");
+            assert!((cam.estimated_max_fps() / full - 4.0).abs() < 1e-4);
+            full
+        };
+        let frame_bytes = (WIDTH * HEIGHT * 2) as f32;
+        let usb2 = fps(false);
+        assert!((usb2 - USB2_BYTES_PER_SEC * 0.5 / frame_bytes).abs() < 1e-2);
+        let usb3 = fps(true);
+        assert!((usb3 - USB3_BYTES_PER_SEC * 0.5 / frame_bytes).abs() < 1e-1);
+    }
+
+    #[test]
+    fn gain_info_representations() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        // default of 100 in the 0-300 range
+        let info = cam.gain_info().expect("Could not read gain");
+        assert_eq!(info.raw, 100);
+        assert!((info.db - 10.0).abs() < 1e-9);
+        assert!((info.percent - 100.0 / 3.0).abs() < 1e-3);
+        assert!(!info.auto);
+        {
+            let mut camera = sdk.camera();
+            camera.set_value(ASI_CONTROL_TYPE_ASI_GAIN, 150);
+            camera
+                .controls
+                .iter_mut()
+                .filter(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_GAIN)
+                .for_each(|(_, _, auto)| *auto = ASI_BOOL_ASI_TRUE);
+        }
+        let info = cam.gain_info().expect("Could not read gain");
+        assert_eq!(info.raw, 150);
+        assert!((info.db - 15.0).abs() < 1e-9);
+        assert!((info.percent - 50.0).abs() < 1e-3);
+        assert!(info.auto);
+        // the cached gain follows the SDK
+        assert_eq!(cam.get_gain().expect("Could not read gain"), 150);
+    }
+
+    #[test]
+    fn adu_to_electrons_follows_gain() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        assert_eq!(cam.adu_to_electrons(100.0), 400.0);
+        // the e-/ADU is read again when the gain changes
+        sdk.camera().info.ElecPerADU = 0.5;
+        cam.set_gain(200).expect("Could not set gain");
+        assert_eq!(cam.adu_to_electrons(100.0), 50.0);
+        assert_eq!(cam.adu_to_electrons(0.0), 0.0);
+    }
+
+    #[test]
+    fn set_centered_roi_aligns_window() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        // shrunk to 32x16, centered on the 64x48 sensor
+        let roi = *cam.set_centered_roi(37, 17, 1).expect("Could not set ROI");
+        assert_eq!(
+            roi,
+            GenCamRoi {
+                x_min: 16,
+                y_min: 16,
+                width: 32,
+                height: 16,
+            }
+        );
+        assert_eq!(sdk.camera().start, (16, 16));
+        // centered on the 32x24 binned sensor
+        let roi = *cam.set_centered_roi(16, 8, 2).expect("Could not set ROI");
+        assert_eq!((roi.x_min, roi.y_min, roi.width, roi.height), (8, 8, 16, 8));
+        assert_eq!(cam.binning(), 2);
+        // the origin stays even
+        let roi = *cam.set_centered_roi(16, 6, 1).expect("Could not set ROI");
+        assert_eq!((roi.x_min, roi.y_min), (24, 20));
+        let calls = sdk.calls("ASISetROIFormat");
+        for (width, height, bin) in [(72, 8, 1), (8, 50, 1), (40, 8, 2), (7, 8, 1), (8, 1, 1)] {
+            assert!(matches!(
+                cam.set_centered_roi(width, height, bin),
+                Err(GenCamError::GeneralError(_))
+            ));
+        }
+        assert!(cam.set_centered_roi(8, 8, 3).is_err());
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+    }
+
+    #[test]
+    fn max_roi_for_supported_bins() {
+        let sdk = mock::install();
+        // 8 and 2 pixel alignment at bin 2 of a 70x50 sensor
+        {
+            let mut camera = sdk.camera();
+            camera.info.MaxWidth = 70;
+            camera.info.MaxHeight = 50;
+        }
+        let cam = open_mock();
+        assert_eq!(
+            cam.max_roi_for_bin(1).expect("Bin 1 not supported"),
+            (64, 50)
+        );
+        assert_eq!(
+            cam.max_roi_for_bin(2).expect("Bin 2 not supported"),
+            (32, 24)
+        );
+        for bin in [0, 3, 4] {
+            assert!(matches!(
+                cam.max_roi_for_bin(bin),
+                Err(GenCamError::GeneralError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn roi_from_device_bypasses_cache() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let cached = *cam.get_roi();
+        assert_eq!(cam.roi_from_device().expect("Could not read ROI"), cached);
+        // the window changed behind the driver's back
+        {
+            let mut camera = sdk.camera();
+            camera.roi = (16, 8, 1, ASI_IMG_TYPE_ASI_IMG_RAW16);
+            camera.start = (8, 4);
+        }
+        let roi = cam.roi_from_device().expect("Could not read ROI");
+        assert_eq!(
+            roi,
+            GenCamRoi {
+                x_min: 8,
+                y_min: 4,
+                width: 16,
+                height: 8,
+            }
+        );
+        assert_eq!(cam.get_roi(), &cached);
+        sdk.fail("ASIGetROIFormat", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(
+            cam.roi_from_device(),
+            Err(GenCamError::CameraClosed)
+        ));
+    }
+
+    #[test]
+    fn capture_with_restores_settings() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let exposure = |sdk: &mock::MockGuard| sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE);
+        let gain = |sdk: &mock::MockGuard| sdk.camera().value(ASI_CONTROL_TYPE_ASI_GAIN);
+        assert_eq!((exposure(&sdk), gain(&sdk)), (Some(10_000), Some(100)));
+        cam.capture_with(Duration::from_millis(1), 200)
+            .expect("Could not capture");
+        let meta = cam.last_frame_metadata().expect("No frame metadata");
+        assert_eq!(meta.exposure, Duration::from_millis(1));
+        assert!((meta.gain.expect("No gain") - 20.0).abs() < 1e-9);
+        assert_eq!((exposure(&sdk), gain(&sdk)), (Some(10_000), Some(100)));
+        // restored after a failed capture too
+        sdk.fail("ASIStartExposure", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(
+            cam.capture_with(Duration::from_millis(2), 250),
+            Err(GenCamError::CameraClosed)
+        ));
+        assert_eq!((exposure(&sdk), gain(&sdk)), (Some(10_000), Some(100)));
+        // out of the 0-300 gain range
+        let calls = sdk.calls("ASISetControlValue");
+        assert!(matches!(
+            cam.capture_with(Duration::from_millis(1), 400),
+            Err(GenCamError::PropertyError { .. })
+        ));
+        assert_eq!(sdk.calls("ASISetControlValue"), calls);
+    }
+
+    #[test]
+    fn apply_preset_sets_controls() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let preset = Preset::new("cold")
+            .with(AnalogCtrl::Gain.into(), PropertyValue::Float(15.0), false)
+            .with(
+                DeviceCtrl::CoolerTemp.into(),
+                PropertyValue::Int(-10),
+                false,
+            );
+        cam.apply_preset(&preset).expect("Could not apply preset");
+        let camera = sdk.camera();
+        assert_eq!(camera.value(ASI_CONTROL_TYPE_ASI_GAIN), Some(150));
+        assert_eq!(camera.value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP), Some(-10));
+        // the other controls are left alone
+        assert_eq!(camera.value(ASI_CONTROL_TYPE_ASI_EXPOSURE), Some(10_000));
+    }
+
+    #[test]
+    fn apply_preset_rolls_back() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let preset = Preset::new("broken")
+            .with(AnalogCtrl::Gain.into(), PropertyValue::Float(15.0), false)
+            .with(
+                DeviceCtrl::Custom("Missing".into()).into(),
+                PropertyValue::Int(1),
+                false,
+            );
+        assert!(matches!(
+            cam.apply_preset(&preset),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::NotFound,
+                ..
+            })
+        ));
+        assert_eq!(sdk.camera().value(ASI_CONTROL_TYPE_ASI_GAIN), Some(100));
+    }
+
+    #[test]
+    fn reset_to_default_applies_manual_default() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        {
+            let mut camera = sdk.camera();
+            camera.set_value(ASI_CONTROL_TYPE_ASI_GAIN, 250);
+            camera.set_value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP, -10);
+            camera
+                .controls
+                .iter_mut()
+                .filter(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_GAIN)
+                .for_each(|(_, _, auto)| *auto = ASI_BOOL_ASI_TRUE);
+        }
+        cam.reset_to_default(&AnalogCtrl::Gain.into())
+            .expect("Could not reset gain");
+        cam.reset_to_default(&DeviceCtrl::CoolerTemp.into())
+            .expect("Could not reset cooler target");
+        {
+            let camera = sdk.camera();
+            let gain = camera
+                .controls
+                .iter()
+                .find(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_GAIN)
+                .expect("No gain control");
+            assert_eq!((gain.1, gain.2), (100, ASI_BOOL_ASI_FALSE));
+            assert_eq!(camera.value(ASI_CONTROL_TYPE_ASI_TARGET_TEMP), Some(0));
+        }
+        assert!(matches!(
+            cam.reset_to_default(&DeviceCtrl::Temperature.into()),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::ReadOnly,
+                ..
+            })
+        ));
+        assert!(matches!(
+            cam.reset_to_default(&DeviceCtrl::Custom("Missing".into()).into()),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::NotFound,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn auto_states_of_auto_controls() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        let exposure: GenCamCtrl = ExposureCtrl::ExposureTime.into();
+        let gain: GenCamCtrl = AnalogCtrl::Gain.into();
+        let states = cam.auto_states().expect("Could not read auto states");
+        assert_eq!(states.get(&exposure), Some(&false));
+        assert_eq!(states.get(&gain), Some(&false));
+        // no auto mode
+        assert!(!states.contains_key(&DeviceCtrl::CoolerTemp.into()));
+        assert!(!states.contains_key(&DeviceCtrl::Temperature.into()));
+        sdk.camera()
+            .controls
+            .iter_mut()
+            .filter(|(caps, _, _)| caps.ControlType == ASI_CONTROL_TYPE_ASI_EXPOSURE)
+            .for_each(|(_, _, auto)| *auto = ASI_BOOL_ASI_TRUE);
+        let states = cam.auto_states().expect("Could not read auto states");
+        assert_eq!(states.get(&exposure), Some(&true));
+        assert_eq!(states.get(&gain), Some(&false));
+    }
+
+    #[test]
+    fn max_exposure_override_clamps() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let exposure: GenCamCtrl = ExposureCtrl::ExposureTime.into();
+        // above the current 10 ms exposure
+        cam.set_max_exposure_override(Duration::from_secs(1))
+            .expect("Could not override the maximum exposure");
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE),
+            Some(10_000)
+        );
+        cam.set_max_exposure_override(Duration::from_millis(5))
+            .expect("Could not override the maximum exposure");
+        assert_eq!(
+            cam.exposure_range().expect("No exposure range"),
+            (Duration::from_micros(32), Duration::from_millis(5))
+        );
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE),
+            Some(5_000)
+        );
+        assert!(matches!(
+            cam.set_property(&exposure, &Duration::from_millis(6).into(), false),
+            Err(GenCamError::PropertyError { .. })
+        ));
+        // below the minimum exposure
+        assert!(matches!(
+            cam.set_max_exposure_override(Duration::from_micros(10)),
+            Err(GenCamError::PropertyError {
+                error: PropertyError::ValueNotSupported,
+                ..
+            })
+        ));
+        assert_eq!(
+            cam.exposure_range().expect("No exposure range").1,
+            Duration::from_millis(5)
+        );
+    }
+
+    #[test]
+    fn download_f32_scales_by_saturation() {
+        let sdk = mock::install();
+        // left-shifted 12-bit samples saturate at 0xfff0
+        sdk.camera().pixel = |i| ((i as u16) & 0xfff) << 4;
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam.download_image_f32().expect("Could not download image");
+        let DynamicImageOwned::F32(data) = img.get_image() else {
+            panic!("Image is not a floating point image");
+        };
+        assert_eq!(data.as_slice().len(), (WIDTH * HEIGHT) as usize);
+        assert!(data
+            .as_slice()
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == (((i as u16) & 0xfff) << 4) as f32 / 65520.0));
+        let roi = *cam.get_roi();
+        cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp8)
+            .expect("Could not set format");
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam.download_image_f32().expect("Could not download image");
+        let DynamicImageOwned::F32(data) = img.get_image() else {
+            panic!("Image is not a floating point image");
+        };
+        assert!(data
+            .as_slice()
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == ((((i as u16) & 0xfff) << 4) as u8) as f32 / 255.0));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn download_into_image_buffer_keeps_depth() {
+        use image::DynamicImage;
+        let sdk = mock::install();
+        sdk.camera().pixel = |i| (i as u16).wrapping_mul(7);
+        let mut cam = open_mock();
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam
+            .download_into_image_buffer()
+            .expect("Could not download image");
+        let DynamicImage::ImageLuma16(img) = img else {
+            panic!("RAW16 frame is not a 16-bit image");
+        };
+        assert_eq!(img.dimensions(), (WIDTH as u32, HEIGHT as u32));
+        assert!(img
+            .as_raw()
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == (i as u16).wrapping_mul(7)));
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 16,
+            height: 8,
+        };
+        cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp8)
+            .expect("Could not set format");
+        cam.start_exposure().expect("Could not start exposure");
+        let img = cam
+            .download_into_image_buffer()
+            .expect("Could not download image");
+        let DynamicImage::ImageLuma8(img) = img else {
+            panic!("RAW8 frame is not an 8-bit image");
+        };
+        assert_eq!(img.dimensions(), (16, 8));
+        assert!(img
+            .as_raw()
+            .iter()
+            .enumerate()
+            .all(|(i, &px)| px == (i as u16).wrapping_mul(7) as u8));
+    }
+
+    #[test]
+    fn supported_formats_are_raw() {
+        {
+            let _sdk = mock::install();
+            let cam = open_mock();
+            assert_eq!(
+                cam.supported_formats(),
+                &[GenCamPixelBpp::Bpp8, GenCamPixelBpp::Bpp16]
+            );
+        }
+        // RGB24 and Y8 are not exposed
+        let sdk = mock::install();
+        sdk.camera().info.SupportedVideoFormat[..4].copy_from_slice(&[
+            ASI_IMG_TYPE_ASI_IMG_RAW8,
+            ASI_IMG_TYPE_ASI_IMG_RGB24,
+            ASI_IMG_TYPE_ASI_IMG_Y8,
+            ASI_IMG_TYPE_ASI_IMG_RAW16,
+        ]);
+        let cam = open_mock();
+        assert_eq!(
+            cam.supported_formats(),
+            &[GenCamPixelBpp::Bpp8, GenCamPixelBpp::Bpp16]
+        );
+    }
+
+    #[test]
+    fn mechanical_shutter_detected() {
+        let shutter: GenCamCtrl = SensorCtrl::ShutterMode.into();
+        {
+            let _sdk = mock::install();
+            let cam = open_mock();
+            assert!(!cam.has_mechanical_shutter());
+            assert!(!cam.get_concat_caps().contains_key(&shutter));
+        }
+        let sdk = mock::install();
+        sdk.camera().info.MechanicalShutter = ASI_BOOL_ASI_TRUE;
+        let mut cam = open_mock();
+        assert!(cam.has_mechanical_shutter());
+        assert!(cam.get_concat_caps().contains_key(&shutter));
+        // frames taken with the shutter closed are dark frames
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert!(cam.last_frame_metadata().expect("No metadata").darkframe);
+        cam.set_property(&shutter, &PropertyValue::Bool(true), false)
+            .expect("Could not open the shutter");
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert!(!cam.last_frame_metadata().expect("No metadata").darkframe);
+    }
+
+    #[test]
+    fn is_color_from_bayer_pattern() {
+        {
+            let _sdk = mock::install();
+            let cam = open_mock();
+            assert!(!cam.is_color());
+            assert!(cam.cspace == ColorSpace::Gray);
+        }
+        let sdk = mock::install();
+        {
+            let mut camera = sdk.camera();
+            camera.info.IsColorCam = ASI_BOOL_ASI_TRUE;
+            camera.info.BayerPattern = ASI_BAYER_PATTERN_ASI_BAYER_GR;
+        }
+        let cam = open_mock();
+        assert!(cam.is_color());
+        assert!(cam.cspace == ColorSpace::from(BayerPattern::Grbg));
+    }
+
+    #[test]
+    fn serial_number_from_sdk() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        assert_eq!(cam.serial_number(), "123456789ABCDEF0");
+        assert_eq!(cam.get_info_handle().serial_number(), "123456789ABCDEF0");
+        drop(cam);
+        let dev = get_asi_devs(true)
+            .expect("Could not list cameras")
+            .remove(0);
+        sdk.fail("ASIGetSerialNumber", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(matches!(
+            open_device(&dev, None),
+            Err(GenCamError::CameraClosed)
+        ));
+    }
+
+    #[test]
+    fn camera_modes_need_trigger_camera() {
+        {
+            let sdk = mock::install();
+            let cam = open_mock();
+            assert!(matches!(
+                cam.supported_modes(),
+                Err(GenCamError::InvalidControlType(_))
+            ));
+            assert_eq!(cam.get_mode().expect("No mode"), AsiCameraMode::Normal);
+            assert!(matches!(
+                cam.set_mode(AsiCameraMode::Normal),
+                Err(GenCamError::InvalidControlType(_))
+            ));
+            assert_eq!(sdk.calls("ASIGetCameraSupportMode"), 0);
+            assert_eq!(sdk.calls("ASISetCameraMode"), 0);
+        }
+        let sdk = mock::install();
+        sdk.camera().info.IsTriggerCam = ASI_BOOL_ASI_TRUE;
+        let cam = open_mock();
+        assert_eq!(
+            cam.supported_modes().expect("No modes"),
+            vec![AsiCameraMode::Normal, AsiCameraMode::SoftEdge]
+        );
+        cam.set_mode(AsiCameraMode::SoftEdge)
+            .expect("Could not set mode");
+        assert_eq!(cam.get_mode().expect("No mode"), AsiCameraMode::SoftEdge);
+        assert!(matches!(
+            cam.set_mode(AsiCameraMode::RiseEdge),
+            Err(GenCamError::InvalidControlType(_))
+        ));
+        assert_eq!(sdk.calls("ASISetCameraMode"), 1);
+    }
+
+    #[test]
+    fn wait_ready_polls_until_done() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        // nothing to wait for
+        assert!(!cam
+            .wait_ready(Duration::from_millis(10))
+            .expect("Could not wait"));
+        sdk.camera().outcome = ASI_EXPOSURE_STATUS_ASI_EXP_WORKING;
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(matches!(
+            cam.wait_ready(Duration::from_millis(20)),
+            Err(GenCamError::TimedOut)
+        ));
+        assert!(cam.is_capturing());
+        let mock: &mock::MockSdk = &sdk;
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                sleep(Duration::from_millis(20));
+                mock.camera().status = ASI_EXPOSURE_STATUS_ASI_EXP_SUCCESS;
+            });
+            assert!(cam
+                .wait_ready(Duration::from_secs(5))
+                .expect("Could not wait"));
+        });
+        cam.download_image().expect("Could not download image");
+    }
+
+    #[test]
+    fn download_progress_reported() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let progress = RefCell::new(Vec::new());
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image_with_progress(|p| progress.borrow_mut().push(p))
+            .expect("Could not download image");
+        assert_eq!(progress.take(), vec![0.0, 1.0]);
+        // no completion on a failed download
+        cam.start_exposure().expect("Could not start exposure");
+        for _ in 0..DOWNLOAD_RETRY_ATTEMPTS {
+            sdk.fail("ASIGetDataAfterExp", ASI_ERROR_CODE_ASI_ERROR_TIMEOUT);
+        }
+        assert!(cam
+            .download_image_with_progress(|p| progress.borrow_mut().push(p))
+            .is_err());
+        assert_eq!(progress.take(), vec![0.0]);
+    }
+
+    #[test]
+    fn is_auto_exposure_follows_set_exposure() {
+        let sdk = mock::install();
+        let cam = open_mock();
+        assert!(!cam.is_auto_exposure().expect("Could not read exposure"));
+        cam.set_exposure(Duration::from_millis(5), true)
+            .expect("Could not set exposure");
+        assert!(cam.is_auto_exposure().expect("Could not read exposure"));
+        cam.set_exposure(Duration::from_millis(5), false)
+            .expect("Could not set exposure");
+        assert!(!cam.is_auto_exposure().expect("Could not read exposure"));
+    }
+
+    #[test]
+    fn set_exposure_clamped_to_range() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let (min, max) = cam.exposure_range().expect("No exposure range");
+        assert_eq!(max, Duration::from_secs(2000));
+        let applied = cam
+            .set_exposure_clamped(Duration::from_secs(3600))
+            .expect("Could not set exposure");
+        assert_eq!(applied, max);
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE),
+            Some(2_000_000_000)
+        );
+        let applied = cam
+            .set_exposure_clamped(Duration::from_micros(1))
+            .expect("Could not set exposure");
+        assert_eq!(applied, min);
+        // the SDK clamps to its own range, so clamp to a narrower one
+        cam.set_max_exposure_override(Duration::from_secs(10))
+            .expect("Could not override the maximum exposure");
+        let applied = cam
+            .set_exposure_clamped(Duration::from_secs(3600))
+            .expect("Could not set exposure");
+        assert_eq!(applied, Duration::from_secs(10));
+        assert_eq!(
+            sdk.camera().value(ASI_CONTROL_TYPE_ASI_EXPOSURE),
+            Some(10_000_000)
+        );
+        // in-range exposures are applied as-is, in manual mode
+        cam.set_exposure(Duration::from_millis(5), true)
+            .expect("Could not set exposure");
+        let applied = cam
+            .set_exposure_clamped(Duration::from_millis(20))
+            .expect("Could not set exposure");
+        assert_eq!(applied, Duration::from_millis(20));
+        assert!(!cam.is_auto_exposure().expect("Could not read exposure"));
+    }
+
+    #[test]
+    fn refresh_info_reads_new_uuid() {
+        let sdk = mock::install();
+        sdk.camera().info.IsUSB3Camera = ASI_BOOL_ASI_TRUE;
+        let mut cam = open_mock();
+        let uuid = |cam: &AsiImager| cam.get_descriptor().info.get("UUID").cloned();
+        assert_eq!(uuid(&cam), None);
+        cam.set_property(
+            &uuid_ctrl(),
+            &PropertyValue::EnumStr("ASI-0001".into()),
+            false,
+        )
+        .expect("Could not set UUID");
+        // the descriptor is stale until refreshed
+        assert_eq!(uuid(&cam), None);
+        let calls = sdk.calls("ASIGetCameraPropertyByID");
+        let desc = cam.refresh_info().expect("Could not refresh info");
+        assert_eq!(
+            desc.info.get("Serial Number").cloned(),
+            Some("123456789ABCDEF0".to_string().into())
+        );
+        assert_eq!(sdk.calls("ASIGetCameraPropertyByID"), calls + 1);
+        assert_eq!(uuid(&cam), Some("ASI-0001".to_string().into()));
+    }
+
+    #[test]
+    fn can_cancel_while_capturing() {
+        let _sdk = mock::install();
+        let mut cam = open_mock();
+        let info = cam.get_info_handle();
+        assert!(!info.can_cancel());
+        cam.start_exposure().expect("Could not start exposure");
+        assert!(info.can_cancel());
+        info.cancel_capture().expect("Could not cancel exposure");
+        assert!(!info.can_cancel());
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        assert!(!info.can_cancel());
+    }
+
+    #[test]
+    fn frame_bytes_byte_order() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        assert_eq!(cam.frame_bytes(false), None);
+        sdk.camera().pixel = |i| 0x0100 + i as u16;
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        let npix = (WIDTH * HEIGHT) as usize;
+        let pixels = cam.imgstor[..npix].to_vec();
+        assert_ne!(pixels[1], pixels[1].swap_bytes());
+        let native = cam.frame_bytes(false).expect("No frame");
+        let big = cam.frame_bytes(true).expect("No frame");
+        assert_eq!(native.len(), npix * 2);
+        assert_eq!(big.len(), npix * 2);
+        for (i, px) in pixels.iter().enumerate() {
+            let at = 2 * i..2 * i + 2;
+            assert_eq!(
+                u16::from_ne_bytes(native[at.clone()].try_into().unwrap()),
+                *px
+            );
+            assert_eq!(u16::from_be_bytes(big[at].try_into().unwrap()), *px);
+        }
+        // 8-bit frames have no byte order
+        let roi = *cam.get_roi();
+        cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp8)
+            .expect("Could not set format");
+        cam.start_exposure().expect("Could not start exposure");
+        cam.download_image().expect("Could not download image");
+        let native = cam.frame_bytes(false).expect("No frame");
+        assert_eq!(native.len(), npix);
+        assert_eq!(cam.frame_bytes(true), Some(native));
+    }
+
+    #[test]
+    fn set_roi_and_format_together() {
+        let sdk = mock::install();
+        let mut cam = open_mock();
+        let roi = GenCamRoi {
+            x_min: 8,
+            y_min: 4,
+            width: 32,
+            height: 16,
+        };
+        let calls = sdk.calls("ASISetROIFormat");
+        cam.set_roi_and_format(&roi, GenCamPixelBpp::Bpp8)
+            .expect("Could not set ROI and format");
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls + 1);
+        assert_eq!(sdk.camera().roi, (32, 16, 1, ASI_IMG_TYPE_ASI_IMG_RAW8));
+        assert_eq!(sdk.camera().start, (8, 4));
+        assert_eq!(cam.get_roi(), &roi);
+        assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+        // a failure changes neither
+        let full = GenCamRoi {
+            x_min: 0,
+            y_min: 0,
+            width: WIDTH as _,
+            height: HEIGHT as _,
+        };
+        sdk.fail("ASISetROIFormat", ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED);
+        assert!(cam
+            .set_roi_and_format(&full, GenCamPixelBpp::Bpp16)
+            .is_err());
+        assert_eq!(sdk.camera().roi, (32, 16, 1, ASI_IMG_TYPE_ASI_IMG_RAW8));
+        assert_eq!(cam.get_roi(), &roi);
+        assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+        // nor does a ROI outside the sensor, which is rejected before the SDK call
+        let calls = sdk.calls("ASISetROIFormat");
+        let outside = GenCamRoi { x_min: 40, ..roi };
+        assert!(cam
+            .set_roi_and_format(&outside, GenCamPixelBpp::Bpp16)
+            .is_err());
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+        assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+    }
+
+    #[test]
+    fn set_unsupported_pixel_format() {
+        let sdk = mock::install();
+        // mono, without 16-bit support
+        sdk.camera().info.SupportedVideoFormat[1] = ASI_IMG_TYPE_ASI_IMG_END;
+        let mut cam = open_mock();
+        assert_eq!(cam.supported_formats(), &[GenCamPixelBpp::Bpp8]);
+        let ctrl: GenCamCtrl = SensorCtrl::PixelFormat.into();
+        let calls = sdk.calls("ASISetROIFormat");
+        match cam.set_property(
+            &ctrl,
+            &PropertyValue::PixelFmt(GenCamPixelBpp::Bpp16),
+            false,
+        ) {
+            Err(GenCamError::PropertyError { control, error }) => {
+                assert_eq!(control, ctrl);
+                assert!(matches!(error, PropertyError::ValueNotSupported));
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // not silently set to RAW8 either
+        assert_eq!(sdk.calls("ASISetROIFormat"), calls);
+        assert_eq!(cam.roi.1, GenCamPixelBpp::Bpp8);
+    }
+
+    #[test]
+    fn temperature_without_cooler() {
+        let sdk = mock::install();
+        {
+            let mut camera = sdk.camera();
+            camera.info.IsCoolerCam = ASI_BOOL_ASI_FALSE;
+            camera.controls.retain(|(caps, _, _)| {
+                ![
+                    ASI_CONTROL_TYPE_ASI_TARGET_TEMP,
+                    ASI_CONTROL_TYPE_ASI_COOLER_ON,
+                    ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC,
+                ]
+                .contains(&caps.ControlType)
+            });
+            camera.set_value(ASI_CONTROL_TYPE_ASI_TEMPERATURE, 215);
+        }
+        let cam = open_mock();
+        let temperature: GenCamCtrl = DeviceCtrl::Temperature.into();
+        let (value, _) = cam
+            .get_property(&temperature)
+            .expect("Could not read temperature");
+        assert_eq!(value, PropertyValue::Float(21.5));
+        assert_eq!(
+            cam.get_temperature().expect("Could not read temperature"),
+            21.5
+        );
+        let info = cam.get_info_handle();
+        let (value, _) = info
+            .get_property(temperature)
+            .expect("Could not read temperature");
+        assert_eq!(value, PropertyValue::Float(21.5));
+        // the cooler controls are not available
+        for ctrl in [
+            DeviceCtrl::CoolerTemp,
+            DeviceCtrl::CoolerEnable,
+            DeviceCtrl::CoolerPower,
+        ] {
+            assert!(matches!(
+                cam.get_property(&ctrl.into()),
+                Err(GenCamError::PropertyError {
+                    error: PropertyError::NotFound,
+                    ..
+                })
+            ));
+        }
+        assert!(info.set_max_cooler_power(50).is_err());
+        assert!(info
+            .on_temperature_stable(-10.0, 1.0, Duration::ZERO, |_| ())
+            .is_err());
+    }
+}
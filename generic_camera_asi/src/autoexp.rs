@@ -0,0 +1,153 @@
+//! Lightweight, dependency-free exposure estimation on raw frames.
+//!
+//! The exposure is scaled so that the [`PERCENTILE`] pixel of the frame lands
+//! at the requested fraction of the 16-bit full scale.
+use std::time::Duration;
+
+/// Percentile of the pixel distribution used to estimate the frame brightness.
+pub const PERCENTILE: f32 = 0.95;
+
+/// Shortest exposure that will be suggested.
+const MIN_EXPOSURE: Duration = Duration::from_micros(32);
+
+/// Largest factor by which the exposure is increased in one step when the frame
+/// is completely dark.
+const MAX_STEP: f64 = 10.0;
+
+/// Suggest an exposure so that the [`PERCENTILE`] pixel of the next frame is at
+/// `target_fraction` of the 16-bit full scale.
+///
+/// - `frame`: Raw 16-bit pixel data of the last frame, taken with `current` exposure.
+/// - `current`: Exposure time of `frame`.
+/// - `target_fraction`: Target brightness as a fraction of the full scale, clamped to `(0, 1]`.
+/// - `max`: Longest exposure that will be suggested.
+///
+/// Returns `current` (clamped to `max`) if `frame` is empty.
+///
+/// # Examples
+/// ```
+/// use generic_camera_asi::autoexp::suggest_exposure;
+/// use std::time::Duration;
+///
+/// let current = Duration::from_millis(100);
+/// let max = Duration::from_secs(10);
+/// // under-exposed frame, exposure is increased
+/// let dark = vec![1000u16; 1024];
+/// assert!(suggest_exposure(&dark, current, 0.5, max) > current);
+/// // over-exposed frame, exposure is decreased
+/// let bright = vec![u16::MAX; 1024];
+/// assert!(suggest_exposure(&bright, current, 0.5, max) < current);
+/// ```
+pub fn suggest_exposure(
+    frame: &[u16],
+    current: Duration,
+    target_fraction: f32,
+    max: Duration,
+) -> Duration {
+    if frame.is_empty() {
+        return current.min(max);
+    }
+    let mut pixels = frame.to_vec();
+    let idx = ((pixels.len() - 1) as f32 * PERCENTILE) as usize;
+    let (_, &mut value, _) = pixels.select_nth_unstable(idx);
+    let target = target_fraction.clamp(f32::EPSILON, 1.0) as f64 * u16::MAX as f64;
+    let scale = if value == 0 {
+        MAX_STEP
+    } else {
+        (target / value as f64).min(MAX_STEP)
+    };
+    current
+        .mul_f64(scale)
+        .clamp(MIN_EXPOSURE, max.max(MIN_EXPOSURE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURRENT: Duration = Duration::from_millis(100);
+    const MAX: Duration = Duration::from_secs(10);
+
+    fn assert_close(actual: Duration, expected: Duration) {
+        let err = (actual.as_secs_f64() - expected.as_secs_f64()).abs();
+        assert!(
+            err <= expected.as_secs_f64() * 1e-3,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn under_exposed_frame() {
+        // half of the target brightness
+        let frame = vec![1 << 14; 1024];
+        assert_close(
+            suggest_exposure(&frame, CURRENT, 0.5, MAX),
+            Duration::from_millis(200),
+        );
+    }
+
+    #[test]
+    fn over_exposed_frame() {
+        let frame = vec![u16::MAX; 1024];
+        assert_close(
+            suggest_exposure(&frame, CURRENT, 0.5, MAX),
+            Duration::from_millis(50),
+        );
+    }
+
+    #[test]
+    fn uses_percentile() {
+        // the brightest 10% of the pixels set the exposure
+        let mut frame = vec![0; 90];
+        frame.extend([1 << 15; 10]);
+        assert_close(
+            suggest_exposure(&frame, CURRENT, 0.5, MAX),
+            Duration::from_millis(100),
+        );
+    }
+
+    #[test]
+    fn empty_frame() {
+        assert_eq!(suggest_exposure(&[], CURRENT, 0.5, MAX), CURRENT);
+        let max = Duration::from_millis(50);
+        assert_eq!(suggest_exposure(&[], CURRENT, 0.5, max), max);
+    }
+
+    #[test]
+    fn step_is_capped() {
+        let dark = vec![0; 1024];
+        assert_close(
+            suggest_exposure(&dark, CURRENT, 0.5, MAX),
+            CURRENT.mul_f64(MAX_STEP),
+        );
+        let dim = vec![1; 1024];
+        assert_close(
+            suggest_exposure(&dim, CURRENT, 0.5, MAX),
+            CURRENT.mul_f64(MAX_STEP),
+        );
+        assert_eq!(suggest_exposure(&dark, CURRENT, 0.5, CURRENT), CURRENT);
+    }
+
+    #[test]
+    fn max_below_min_exposure() {
+        let frame = vec![u16::MAX; 1024];
+        let max = Duration::from_micros(1);
+        assert_eq!(suggest_exposure(&frame, CURRENT, 0.5, max), MIN_EXPOSURE);
+        let dark = vec![0; 1024];
+        assert_eq!(suggest_exposure(&dark, CURRENT, 0.5, max), MIN_EXPOSURE);
+    }
+
+    #[test]
+    fn target_fraction_clamped() {
+        let frame = vec![1 << 15; 1024];
+        assert_eq!(
+            suggest_exposure(&frame, CURRENT, 2.0, MAX),
+            suggest_exposure(&frame, CURRENT, 1.0, MAX)
+        );
+        for target in [0.0, -1.0] {
+            assert_eq!(suggest_exposure(&frame, CURRENT, target, MAX), MIN_EXPOSURE);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Helpers to calibrate frames captured with ASI cameras.
+use generic_camera::{GenCamError, GenCamResult};
+use refimage::{DynamicImageOwned, GenericImage, ImageProps};
+
+/// Subtract a master dark frame from `frame` in place.
+///
+/// The dark frame must have the same dimensions, number of channels and pixel
+/// type as `frame`, otherwise [`GenCamError::InvalidFormat`] is returned.
+/// Integer pixels are clamped at zero; floating point pixels are not clamped.
+pub fn subtract_dark(frame: &mut GenericImage, dark: &GenericImage) -> GenCamResult<()> {
+    if frame.width() != dark.width()
+        || frame.height() != dark.height()
+        || frame.channels() != dark.channels()
+    {
+        return Err(GenCamError::InvalidFormat(format!(
+            "Dark frame is {}x{}x{}, expected {}x{}x{}",
+            dark.width(),
+            dark.height(),
+            dark.channels(),
+            frame.width(),
+            frame.height(),
+            frame.channels()
+        )));
+    }
+    let (frame_type, dark_type) = (frame.pixel_type(), dark.pixel_type());
+    match (frame.get_image_mut(), dark.get_image()) {
+        (DynamicImageOwned::U8(img), DynamicImageOwned::U8(dark)) => {
+            img.as_mut_slice()
+                .iter_mut()
+                .zip(dark.as_slice())
+                .for_each(|(px, d)| *px = px.saturating_sub(*d));
+        }
+        (DynamicImageOwned::U16(img), DynamicImageOwned::U16(dark)) => {
+            img.as_mut_slice()
+                .iter_mut()
+                .zip(dark.as_slice())
+                .for_each(|(px, d)| *px = px.saturating_sub(*d));
+        }
+        (DynamicImageOwned::F32(img), DynamicImageOwned::F32(dark)) => {
+            img.as_mut_slice()
+                .iter_mut()
+                .zip(dark.as_slice())
+                .for_each(|(px, d)| *px -= d);
+        }
+        _ => {
+            return Err(GenCamError::InvalidFormat(format!(
+                "Dark frame pixel type {:?} does not match frame pixel type {:?}",
+                dark_type, frame_type
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Find the hot pixels in a dark frame, i.e. the pixels with a value above `threshold`.
+///
+/// Returns the `(x, y)` coordinates of the hot pixels, in row-major order. For
+/// multi-channel frames, a pixel is hot if any of its channels is above the threshold.
+/// Floating point frames are compared against `threshold` as is.
+pub fn find_hot_pixels(dark: &GenericImage, threshold: u16) -> Vec<(u32, u32)> {
+    let width = dark.width();
+    let channels = dark.channels() as usize;
+    let hot: Vec<bool> = match dark.get_image() {
+        DynamicImageOwned::U8(img) => img
+            .as_slice()
+            .iter()
+            .map(|&px| px as u16 > threshold)
+            .collect(),
+        DynamicImageOwned::U16(img) => img.as_slice().iter().map(|&px| px > threshold).collect(),
+        DynamicImageOwned::F32(img) => img
+            .as_slice()
+            .iter()
+            .map(|&px| px > threshold as f32)
+            .collect(),
+    };
+    hot.chunks(channels.max(1))
+        .enumerate()
+        .filter(|(_, px)| px.iter().any(|&hot| hot))
+        .map(|(idx, _)| ((idx % width) as u32, (idx / width) as u32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use refimage::{ColorSpace, ImageOwned};
+
+    use super::*;
+
+    fn image_u16(data: Vec<u16>, width: usize, height: usize) -> GenericImage {
+        let img = ImageOwned::new(data, width, height, ColorSpace::Gray).unwrap();
+        GenericImage::new(SystemTime::now(), DynamicImageOwned::U16(img))
+    }
+
+    fn pixels_u16(img: &GenericImage) -> &[u16] {
+        match img.get_image() {
+            DynamicImageOwned::U16(img) => img.as_slice(),
+            _ => panic!("Pixel type changed"),
+        }
+    }
+
+    #[test]
+    fn subtract_dark_saturates_at_zero() {
+        let mut frame = image_u16(vec![100, 5, 0, u16::MAX], 2, 2);
+        let dark = image_u16(vec![10, 10, 1, u16::MAX], 2, 2);
+        subtract_dark(&mut frame, &dark).unwrap();
+        assert_eq!(pixels_u16(&frame), &[90, 0, 0, 0]);
+    }
+
+    #[test]
+    fn subtract_dark_float_is_not_clamped() {
+        let img = ImageOwned::new(vec![0.25f32, 0.5], 2, 1, ColorSpace::Gray).unwrap();
+        let mut frame = GenericImage::new(SystemTime::now(), DynamicImageOwned::F32(img));
+        let img = ImageOwned::new(vec![0.5f32, 0.25], 2, 1, ColorSpace::Gray).unwrap();
+        let dark = GenericImage::new(SystemTime::now(), DynamicImageOwned::F32(img));
+        subtract_dark(&mut frame, &dark).unwrap();
+        let DynamicImageOwned::F32(img) = frame.get_image() else {
+            panic!("Pixel type changed");
+        };
+        assert_eq!(img.as_slice(), &[-0.25, 0.25]);
+    }
+
+    #[test]
+    fn subtract_dark_rejects_mismatched_dimensions() {
+        let mut frame = image_u16(vec![100; 4], 2, 2);
+        let dark = image_u16(vec![10; 4], 4, 1);
+        assert!(matches!(
+            subtract_dark(&mut frame, &dark),
+            Err(GenCamError::InvalidFormat(_))
+        ));
+        // the frame is left untouched
+        assert_eq!(pixels_u16(&frame), &[100; 4]);
+    }
+
+    #[test]
+    fn subtract_dark_rejects_mismatched_pixel_type() {
+        let mut frame = image_u16(vec![100; 4], 2, 2);
+        let img = ImageOwned::new(vec![10u8; 4], 2, 2, ColorSpace::Gray).unwrap();
+        let dark = GenericImage::new(SystemTime::now(), DynamicImageOwned::U8(img));
+        assert!(matches!(
+            subtract_dark(&mut frame, &dark),
+            Err(GenCamError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn hot_pixels_strictly_above_threshold() {
+        let dark = image_u16(vec![99, 100, 101, 0, u16::MAX, 100], 3, 2);
+        assert_eq!(find_hot_pixels(&dark, 100), vec![(2, 0), (1, 1)]);
+        assert_eq!(
+            find_hot_pixels(&dark, 98),
+            vec![(0, 0), (1, 0), (2, 0), (1, 1), (2, 1)]
+        );
+        assert!(find_hot_pixels(&dark, u16::MAX).is_empty());
+    }
+
+    #[test]
+    fn hot_pixels_in_8_bit_dark() {
+        let img = ImageOwned::new(vec![0u8, 200, u8::MAX, 10], 2, 2, ColorSpace::Gray).unwrap();
+        let dark = GenericImage::new(SystemTime::now(), DynamicImageOwned::U8(img));
+        assert_eq!(find_hot_pixels(&dark, 199), vec![(1, 0), (0, 1)]);
+        // no 8-bit pixel is above a threshold past its range
+        assert!(find_hot_pixels(&dark, 255).is_empty());
+        assert!(find_hot_pixels(&dark, 1000).is_empty());
+    }
+}
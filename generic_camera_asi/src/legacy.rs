@@ -0,0 +1,164 @@
+//! Conversion to the [`DynamicSerialImage`] of the `serialimage` crate, for
+//! pipelines migrating from `cameraunit_asi`.
+use generic_camera::{GenCamError, GenCamResult, GenericImage};
+use refimage::{DynamicImageOwned, ImageProps};
+use serialimage::{DynamicSerialImage, ImageMetaData, SerialImageBuffer};
+
+use crate::FrameMetadata;
+
+/// Convert a downloaded frame to a [`DynamicSerialImage`], with the legacy metadata.
+///
+/// The binning, ROI origin, temperature, exposure, timestamp and gain (in the raw
+/// 0.1 dB units of the legacy API) are stored in the [`ImageMetaData`] fields;
+/// the frame serial number, frame type, electrons per ADU and flips are stored as
+/// extended attributes, under the same keys as in the [`GenericImage`]. The offset
+/// and gain limits are not part of the frame metadata, and are left at zero.
+///
+/// # Errors
+/// [`GenCamError::InvalidFormat`] if the pixel data does not fit the dimensions of
+/// the frame.
+pub fn to_dynamic_serial_image(
+    img: &GenericImage,
+    meta: &FrameMetadata,
+    camera_name: &str,
+) -> GenCamResult<DynamicSerialImage> {
+    let (width, height) = (img.width(), img.height());
+    let legacy = Some(legacy_metadata(meta, camera_name));
+    let invalid = |e: &str| GenCamError::InvalidFormat(e.to_string());
+    let img = match img.get_image() {
+        DynamicImageOwned::U8(img) => {
+            let mut img = SerialImageBuffer::from_vec(width, height, img.as_slice().to_vec())
+                .map_err(invalid)?;
+            img.set_metadata(legacy);
+            DynamicSerialImage::from(img)
+        }
+        DynamicImageOwned::U16(img) => {
+            let mut img = SerialImageBuffer::from_vec(width, height, img.as_slice().to_vec())
+                .map_err(invalid)?;
+            img.set_metadata(legacy);
+            DynamicSerialImage::from(img)
+        }
+        DynamicImageOwned::F32(img) => {
+            let mut img = SerialImageBuffer::from_vec(width, height, img.as_slice().to_vec())
+                .map_err(invalid)?;
+            img.set_metadata(legacy);
+            DynamicSerialImage::from(img)
+        }
+    };
+    Ok(img)
+}
+
+fn legacy_metadata(meta: &FrameMetadata, camera_name: &str) -> ImageMetaData {
+    let mut out = ImageMetaData::full_builder(
+        meta.bin as _,
+        meta.bin as _,
+        meta.roi.y_min as _,
+        meta.roi.x_min as _,
+        meta.temperature.unwrap_or(-273.16),
+        meta.exposure,
+        meta.timestamp,
+        camera_name,
+        meta.gain
+            .map(|gain| (gain * 10.0).round() as i64)
+            .unwrap_or(0),
+        0,
+        0,
+        0,
+    );
+    out.add_extended_attrib("IMGSER", &meta.serial.to_string());
+    out.add_extended_attrib("IMAGETYP", if meta.darkframe { "Dark" } else { "Light" });
+    out.add_extended_attrib("ADU2ELEC", &meta.electrons_per_adu.to_string());
+    if let Some((flip_x, flip_y)) = meta.flip {
+        out.add_extended_attrib("FLIPX", &flip_x.to_string());
+        out.add_extended_attrib("FLIPY", &flip_y.to_string());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use generic_camera::{GenCamPixelBpp, GenCamRoi};
+    use refimage::{ColorSpace, ImageOwned};
+
+    use super::*;
+
+    fn metadata() -> FrameMetadata {
+        FrameMetadata {
+            serial: 7,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            exposure: Duration::from_millis(250),
+            darkframe: true,
+            gain: Some(12.3),
+            electrons_per_adu: 4.0,
+            temperature: Some(-10.5),
+            roi: GenCamRoi {
+                x_min: 16,
+                y_min: 8,
+                width: 4,
+                height: 2,
+            },
+            pixel_format: GenCamPixelBpp::Bpp16,
+            bin: 2,
+            flip: Some((true, false)),
+        }
+    }
+
+    #[test]
+    fn metadata_survives_conversion() {
+        let meta = metadata();
+        let data: Vec<u16> = (0..8).collect();
+        let img = ImageOwned::new(data.clone(), 4, 2, ColorSpace::Gray).unwrap();
+        let img = GenericImage::new(meta.timestamp, DynamicImageOwned::U16(img));
+        let DynamicSerialImage::U16(img) =
+            to_dynamic_serial_image(&img, &meta, "ZWO ASI120MM Mini").unwrap()
+        else {
+            panic!("Pixel type was not kept");
+        };
+        assert_eq!((img.width(), img.height()), (4, 2));
+        assert_eq!(img.get_luma(), Some(&data));
+        let legacy = img.get_metadata().expect("No metadata");
+        assert_eq!((legacy.bin_x, legacy.bin_y), (2, 2));
+        assert_eq!((legacy.img_left, legacy.img_top), (16, 8));
+        assert_eq!(legacy.temperature, -10.5);
+        assert_eq!(legacy.exposure, meta.exposure);
+        assert_eq!(legacy.timestamp, meta.timestamp);
+        assert_eq!(legacy.camera_name, "ZWO ASI120MM Mini");
+        assert_eq!(legacy.gain, 123);
+        let attrib = |key: &str| {
+            legacy
+                .get_extended_data()
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(attrib("IMGSER"), Some("7"));
+        assert_eq!(attrib("IMAGETYP"), Some("Dark"));
+        assert_eq!(attrib("ADU2ELEC"), Some("4"));
+        assert_eq!(attrib("FLIPX"), Some("true"));
+        assert_eq!(attrib("FLIPY"), Some("false"));
+    }
+
+    #[test]
+    fn missing_values_use_legacy_defaults() {
+        let meta = FrameMetadata {
+            gain: None,
+            temperature: None,
+            flip: None,
+            ..metadata()
+        };
+        let img = ImageOwned::new(vec![0u8; 8], 4, 2, ColorSpace::Gray).unwrap();
+        let img = GenericImage::new(meta.timestamp, DynamicImageOwned::U8(img));
+        let DynamicSerialImage::U8(img) = to_dynamic_serial_image(&img, &meta, "").unwrap() else {
+            panic!("Pixel type was not kept");
+        };
+        let legacy = img.get_metadata().expect("No metadata");
+        assert_eq!(legacy.gain, 0);
+        assert_eq!(legacy.temperature, -273.16);
+        assert!(!legacy
+            .get_extended_data()
+            .iter()
+            .any(|(k, _)| k.starts_with("FLIP")));
+    }
+}
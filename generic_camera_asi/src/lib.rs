@@ -9,7 +9,7 @@
 //! use generic_camera_asi::{GenCamAsi, GenCamDriverAsi};
 //! use std::{thread::sleep, time::Duration};
 //!  
-//! let mut drv = GenCamDriverAsi;
+//! let mut drv = GenCamDriverAsi::default();
 //! if drv.available_devices() == 0 {
 //!     return;
 //! }
@@ -24,12 +24,27 @@
 //! ```
 mod asicamera2;
 mod asihandle;
+#[cfg(feature = "autoexp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "autoexp")))]
+pub mod autoexp;
+pub mod calibration;
+#[cfg(feature = "serialimage")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serialimage")))]
+pub mod legacy;
+#[cfg(feature = "shmem")]
+#[cfg_attr(docsrs, doc(cfg(feature = "shmem")))]
+pub mod shmem;
 mod zwo_ffi;
 #[macro_use]
 mod zwo_ffi_wrapper;
+mod zwo_sdk;
 
-pub use asicamera2::{GenCamAsi, GenCamDriverAsi};
-pub use asihandle::GenCamInfoAsi;
+pub use asicamera2::{GenCamAsi, GenCamDriverAsi, RetryPolicy, StreamHandle};
+pub use asihandle::{
+    CameraSettings, CornerStats, DataAlignment, FrameMetadata, FrameStats, GainInfo, GenCamInfoAsi,
+    Preset, TimestampSource,
+};
+pub use zwo_ffi_wrapper::{AsiCameraMode, AsiErrorCode, ControlUnit, GuideDir, UsbSpeed};
 
 pub use generic_camera::*;
 
@@ -0,0 +1,353 @@
+//! Shared-memory ring of frames, to hand frames to other processes.
+//!
+//! The ring is a memory-mapped file named after the ring, in `/dev/shm` on Linux
+//! and in the temporary directory elsewhere. It holds a fixed number of slots, each
+//! large enough for a full frame; frames are written to the slots in turn, and a
+//! reader picks up the latest complete frame.
+//!
+//! # Layout
+//! All integers are in native byte order.
+//! - Header (64 bytes): magic `b"ASIRING\0"`, version (`u32`), number of slots (`u32`),
+//!   slot size in bytes (`u64`), sequence number of the last written frame (`u64`).
+//! - Slots, each starting with a 32 byte header: sequence number of the frame in the
+//!   slot (`u64`, `u64::MAX` while it is written), width, height and bytes per pixel
+//!   (`u32` each), padding (`u32`), length of the pixel data in bytes (`u64`);
+//!   followed by the pixel data.
+//!
+//! The slot headers and pixel data are copied in 8-byte words with atomic loads and
+//! stores, so that a reader racing with the writer reads stale or mixed words rather
+//! than causing undefined behavior; such a torn read is detected by checking the
+//! sequence number of the slot again after the copy.
+//!
+//! # Examples
+//! ```
+//! use generic_camera_asi::shmem::ShmemRing;
+//!
+//! let name = format!("asi-doctest-{}", std::process::id());
+//! let mut ring = ShmemRing::create(&name, 2, 64).expect("Could not create ring");
+//! let data: Vec<u8> = (0..32).collect();
+//! ring.write(4, 4, 2, &data).expect("Could not write frame");
+//!
+//! let reader = ShmemRing::open(&name).expect("Could not open ring");
+//! let frame = reader.latest().expect("No frame in the ring");
+//! assert_eq!((frame.width, frame.height, frame.bytes_per_pixel), (4, 4, 2));
+//! assert_eq!(frame.data, data);
+//! ```
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::PathBuf,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+use memmap2::MmapMut;
+
+const MAGIC: [u8; 8] = *b"ASIRING\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 32;
+/// Sequence number of a slot that is being written.
+const WRITING: u64 = u64::MAX;
+
+/// A frame read from a [`ShmemRing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShmemFrame {
+    /// Sequence number of the frame, starting at 1.
+    pub seq: u64,
+    /// Width of the frame, in pixels.
+    pub width: u32,
+    /// Height of the frame, in pixels.
+    pub height: u32,
+    /// Bytes per pixel (1 or 2).
+    pub bytes_per_pixel: u32,
+    /// Pixel data, in native byte order.
+    pub data: Vec<u8>,
+}
+
+/// A ring of frames in named shared memory.
+///
+/// The process that [creates](ShmemRing::create) the ring writes to it, and removes
+/// the backing file when the ring is dropped. Other processes [open](ShmemRing::open)
+/// the ring to read frames.
+#[derive(Debug)]
+pub struct ShmemRing {
+    path: PathBuf,
+    map: MmapMut,
+    slots: u32,
+    slot_size: u64,
+    owner: bool,
+}
+
+fn ring_path(name: &str) -> PathBuf {
+    #[cfg(target_os = "linux")]
+    let dir = PathBuf::from("/dev/shm");
+    #[cfg(not(target_os = "linux"))]
+    let dir = std::env::temp_dir();
+    dir.join(name)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl ShmemRing {
+    /// Create the ring `name`, with `slots` slots of up to `frame_size` bytes of pixel data.
+    ///
+    /// An existing ring with the same name is replaced by a new file. Readers that
+    /// still have the old ring open keep reading it, and must open the ring again
+    /// to follow the new one.
+    pub fn create(name: &str, slots: usize, frame_size: usize) -> io::Result<Self> {
+        if slots == 0 || slots > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Number of slots must be between 1 and 2^32 - 1",
+            ));
+        }
+        // keep the slot headers 8-byte aligned
+        let slot_size = (SLOT_HEADER_SIZE + frame_size + 7) & !7;
+        let len = slot_size
+            .checked_mul(slots)
+            .and_then(|len| len.checked_add(HEADER_SIZE))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Ring is too large"))?;
+        let path = ring_path(name);
+        // resizing the old file would fault the readers that still map it
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        file.set_len(len as u64)?;
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..8].copy_from_slice(&MAGIC);
+        map[8..12].copy_from_slice(&VERSION.to_ne_bytes());
+        map[12..16].copy_from_slice(&(slots as u32).to_ne_bytes());
+        map[16..24].copy_from_slice(&(slot_size as u64).to_ne_bytes());
+        Ok(Self {
+            path,
+            map,
+            slots: slots as u32,
+            slot_size: slot_size as u64,
+            owner: true,
+        })
+    }
+
+    /// Open the existing ring `name` for reading.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let path = ring_path(name);
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Self::from_file(path, &file)
+    }
+
+    fn from_file(path: PathBuf, file: &File) -> io::Result<Self> {
+        let map = unsafe { MmapMut::map_mut(file)? };
+        if map.len() < HEADER_SIZE || map[..8] != MAGIC {
+            return Err(invalid("Not a frame ring"));
+        }
+        let version = u32::from_ne_bytes(map[8..12].try_into().expect("4 bytes"));
+        if version != VERSION {
+            return Err(invalid("Unsupported frame ring version"));
+        }
+        let slots = u32::from_ne_bytes(map[12..16].try_into().expect("4 bytes"));
+        let slot_size = u64::from_ne_bytes(map[16..24].try_into().expect("8 bytes"));
+        if (HEADER_SIZE as u64 + slots as u64 * slot_size) > map.len() as u64 {
+            return Err(invalid("Frame ring is truncated"));
+        }
+        Ok(Self {
+            path,
+            map,
+            slots,
+            slot_size,
+            owner: false,
+        })
+    }
+
+    /// Get the number of slots in the ring.
+    pub fn slots(&self) -> usize {
+        self.slots as _
+    }
+
+    /// Get the largest frame (in bytes) a slot can hold.
+    pub fn frame_size(&self) -> usize {
+        self.slot_size as usize - SLOT_HEADER_SIZE
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        // the map is page aligned, and all atomics are at 8-byte aligned offsets
+        unsafe { &*(self.map.as_ptr().add(offset) as *const AtomicU64) }
+    }
+
+    /// Copy `data` to `offset` in 8-byte words, zero-padding the last word.
+    fn store_words(&self, offset: usize, data: &[u8]) {
+        for (i, chunk) in data.chunks(8).enumerate() {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.atomic(offset + 8 * i)
+                .store(u64::from_ne_bytes(word), Ordering::Relaxed);
+        }
+    }
+
+    /// Copy `len` bytes from `offset` in 8-byte words.
+    fn load_words(&self, offset: usize, len: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(len + 7);
+        for i in (0..len).step_by(8) {
+            let word = self.atomic(offset + i).load(Ordering::Relaxed);
+            out.extend_from_slice(&word.to_ne_bytes());
+        }
+        out.truncate(len);
+        out
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        self.atomic(24)
+    }
+
+    fn slot_offset(&self, seq: u64) -> usize {
+        HEADER_SIZE + ((seq - 1) % self.slots as u64 * self.slot_size) as usize
+    }
+
+    /// Write a frame to the next slot, returning its sequence number.
+    pub fn write(
+        &mut self,
+        width: u32,
+        height: u32,
+        bytes_per_pixel: u32,
+        data: &[u8],
+    ) -> io::Result<u64> {
+        if data.len() > self.frame_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Frame does not fit in a ring slot",
+            ));
+        }
+        let seq = self.head().load(Ordering::Acquire) + 1;
+        let offset = self.slot_offset(seq);
+        self.atomic(offset).store(WRITING, Ordering::Relaxed);
+        // keep the slot writes after the slot is marked as being written
+        fence(Ordering::Release);
+        let mut hdr = [0u8; SLOT_HEADER_SIZE - 8];
+        hdr[..4].copy_from_slice(&width.to_ne_bytes());
+        hdr[4..8].copy_from_slice(&height.to_ne_bytes());
+        hdr[8..12].copy_from_slice(&bytes_per_pixel.to_ne_bytes());
+        hdr[16..24].copy_from_slice(&(data.len() as u64).to_ne_bytes());
+        self.store_words(offset + 8, &hdr);
+        // slots are a multiple of 8 bytes, so the padded last word fits
+        self.store_words(offset + SLOT_HEADER_SIZE, data);
+        self.atomic(offset).store(seq, Ordering::Release);
+        self.head().store(seq, Ordering::Release);
+        Ok(seq)
+    }
+
+    /// Read the latest complete frame, if any.
+    ///
+    /// Returns `None` if no frame has been written yet, or if the slot was being
+    /// overwritten while it was read.
+    pub fn latest(&self) -> Option<ShmemFrame> {
+        let seq = self.head().load(Ordering::Acquire);
+        if seq == 0 {
+            return None;
+        }
+        let offset = self.slot_offset(seq);
+        if self.atomic(offset).load(Ordering::Acquire) != seq {
+            return None;
+        }
+        let hdr = self.load_words(offset + 8, SLOT_HEADER_SIZE - 8);
+        let width = u32::from_ne_bytes(hdr[..4].try_into().expect("4 bytes"));
+        let height = u32::from_ne_bytes(hdr[4..8].try_into().expect("4 bytes"));
+        let bytes_per_pixel = u32::from_ne_bytes(hdr[8..12].try_into().expect("4 bytes"));
+        let len = u64::from_ne_bytes(hdr[16..24].try_into().expect("8 bytes")) as usize;
+        let data = self.load_words(offset + SLOT_HEADER_SIZE, len.min(self.frame_size()));
+        // the writer may have lapped the ring while the data was copied; keep the
+        // copy before the check
+        fence(Ordering::Acquire);
+        if self.atomic(offset).load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        Some(ShmemFrame {
+            seq,
+            width,
+            height,
+            bytes_per_pixel,
+            data,
+        })
+    }
+}
+
+impl Drop for ShmemRing {
+    fn drop(&mut self) {
+        if self.owner {
+            // readers keep their mapping until they drop it
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_reader_sees_complete_frames() {
+        const FRAMES: u64 = 2000;
+        const SIZE: usize = 4096;
+        let name = format!("asi-test-ring-{}", std::process::id());
+        let mut ring = ShmemRing::create(&name, 2, SIZE).expect("Could not create ring");
+        let reader = ShmemRing::open(&name).expect("Could not open ring");
+        let writer = std::thread::spawn(move || {
+            for seq in 1..=FRAMES {
+                // every byte of a frame, and its width, identify the frame
+                let data = vec![seq as u8; SIZE];
+                assert_eq!(ring.write(seq as u32, 1, 1, &data).unwrap(), seq);
+            }
+            ring
+        });
+        let mut last = 0;
+        while last < FRAMES {
+            let Some(frame) = reader.latest() else {
+                continue;
+            };
+            assert!(frame.seq >= last, "Sequence went backwards");
+            assert_eq!(frame.width as u64, frame.seq, "Torn header");
+            assert!(
+                frame.data.iter().all(|&b| b == frame.seq as u8),
+                "Torn frame {}",
+                frame.seq
+            );
+            last = frame.seq;
+        }
+        drop(writer.join().expect("Writer panicked"));
+    }
+
+    #[test]
+    fn create_replaces_ring() {
+        let name = format!("asi-test-ring-replace-{}", std::process::id());
+        let mut old = ShmemRing::create(&name, 2, 16).expect("Could not create ring");
+        old.write(2, 2, 1, &[1; 4]).expect("Could not write frame");
+        let reader = ShmemRing::open(&name).expect("Could not open ring");
+        let mut new = ShmemRing::create(&name, 4, 64).expect("Could not create ring");
+        new.write(4, 4, 1, &[2; 16]).expect("Could not write frame");
+        // the old mapping is still valid, and unchanged
+        assert_eq!(reader.slots(), 2);
+        assert_eq!(reader.latest().expect("No frame in the ring").data, [1; 4]);
+        let reader = ShmemRing::open(&name).expect("Could not open ring");
+        assert_eq!(reader.slots(), 4);
+        assert_eq!(reader.latest().expect("No frame in the ring").data, [2; 16]);
+    }
+
+    #[test]
+    fn odd_frame_length() {
+        let name = format!("asi-test-ring-odd-{}", std::process::id());
+        let mut ring = ShmemRing::create(&name, 1, 13).expect("Could not create ring");
+        let data: Vec<u8> = (1..=13).collect();
+        ring.write(13, 1, 1, &data).expect("Could not write frame");
+        let frame = ring.latest().expect("No frame in the ring");
+        assert_eq!(
+            (frame.width, frame.height, frame.bytes_per_pixel),
+            (13, 1, 1)
+        );
+        assert_eq!(frame.data, data);
+    }
+}
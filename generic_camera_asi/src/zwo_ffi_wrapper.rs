@@ -2,6 +2,7 @@ use std::{
     collections::HashMap,
     fmt::{Debug, Display},
     os::raw,
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 
@@ -21,7 +22,7 @@ macro_rules! ASICALL {
     ($func:ident($($arg:expr),*)) => {
         (|| -> Result<(), $crate::zwo_ffi_wrapper::AsiError> {
             #[allow(clippy::macro_metavars_in_unsafe)]
-            let res = unsafe { $func($($arg),*) };
+            let res = unsafe { $crate::zwo_sdk::sdk().$func($($arg),*) };
             if res != $crate::zwo_ffi::ASI_ERROR_CODE_ASI_SUCCESS as _ {
                 #[cfg(debug_assertions)]
                 let err = {
@@ -42,6 +43,47 @@ macro_rules! ASICALL {
     };
 }
 
+#[macro_export]
+/// Generate a closure that wraps an ASI function call like [`ASICALL!`], retrying
+/// the call up to `attempts` times (waiting `delay` in between) while it fails with
+/// a transient error.
+///
+/// By default, [`AsiError::Timeout`] and [`AsiError::GeneralError`] are considered
+/// transient. A different set can be given as a pattern in the last argument.
+macro_rules! ASICALL_RETRY {
+    ($func:ident($($arg:expr),*), $attempts:expr, $delay:expr) => {
+        $crate::ASICALL_RETRY!(
+            $func($($arg),*),
+            $attempts,
+            $delay,
+            $crate::zwo_ffi_wrapper::AsiError::Timeout(..)
+                | $crate::zwo_ffi_wrapper::AsiError::GeneralError(..)
+        )
+    };
+    ($func:ident($($arg:expr),*), $attempts:expr, $delay:expr, $transient:pat) => {
+        (|| -> Result<(), $crate::zwo_ffi_wrapper::AsiError> {
+            let attempts: usize = $attempts;
+            let mut attempt = 1;
+            loop {
+                match $crate::ASICALL!($func($($arg),*)) {
+                    Err(e) if attempt < attempts && matches!(e, $transient) => {
+                        log::warn!(
+                            "Retrying {} ({}/{}): {}",
+                            stringify!($func),
+                            attempt,
+                            attempts,
+                            e
+                        );
+                        std::thread::sleep($delay);
+                        attempt += 1;
+                    }
+                    res => return res,
+                }
+            }
+        })()
+    };
+}
+
 impl Default for ASI_CAMERA_INFO {
     fn default() -> Self {
         Self {
@@ -92,6 +134,15 @@ impl Default for ASI_CONTROL_CAPS {
     }
 }
 
+impl Default for ASI_SUPPORTED_MODE {
+    fn default() -> Self {
+        Self {
+            SupportedCameraMode: [ASI_CAMERA_MODE_ASI_MODE_END; 16],
+            Unused: [0; 32],
+        }
+    }
+}
+
 pub(crate) fn string_from_char<const N: usize>(inp: &[raw::c_char; N]) -> String {
     let mut str = String::from_utf8_lossy(&unsafe {
         std::mem::transmute_copy::<[raw::c_char; N], [u8; N]>(inp)
@@ -101,6 +152,51 @@ pub(crate) fn string_from_char<const N: usize>(inp: &[raw::c_char; N]) -> String
     str.trim().to_string()
 }
 
+/// Sensors of ASI cameras, by model number (the part of the name after "ASI").
+/// More specific models come before the models they start with.
+const SENSOR_MODELS: &[(&str, &str)] = &[
+    ("071", "IMX071"),
+    ("094", "IMX094"),
+    ("120", "AR0130CS"),
+    ("128", "IMX128"),
+    ("174", "IMX174"),
+    ("178", "IMX178"),
+    ("183", "IMX183"),
+    ("224", "IMX224"),
+    ("290", "IMX290"),
+    ("294MM", "IMX492"),
+    ("294", "IMX294"),
+    ("385", "IMX385"),
+    ("432", "IMX432"),
+    ("462", "IMX462"),
+    ("464", "IMX464"),
+    ("482", "IMX482"),
+    ("485", "IMX485"),
+    ("533", "IMX533"),
+    ("585", "IMX585"),
+    ("662", "IMX662"),
+    ("676", "IMX676"),
+    ("678", "IMX678"),
+    ("715", "IMX715"),
+    ("1600", "MN34230"),
+    ("2400", "IMX410"),
+    ("2600", "IMX571"),
+    ("6200", "IMX455"),
+];
+
+/// Look up the sensor of a camera from its name, e.g. "ZWO ASI533MC Pro".
+pub(crate) fn sensor_model(name: &str) -> Option<&'static str> {
+    let (_, model) = name.split_once("ASI")?;
+    SENSOR_MODELS
+        .iter()
+        .find(|(prefix, _)| {
+            // the model number must not continue, e.g. "120" does not match "1200"
+            model.starts_with(prefix)
+                && !model[prefix.len()..].starts_with(|c: char| c.is_ascii_digit())
+        })
+        .map(|(_, sensor)| *sensor)
+}
+
 impl From<ASI_CAMERA_INFO> for GenCamDescriptor {
     fn from(value: ASI_CAMERA_INFO) -> Self {
         let name = string_from_char(&value.Name);
@@ -149,6 +245,13 @@ impl From<ASI_CAMERA_INFO> for GenCamDescriptor {
             "USB3 Device".to_string(),
             (value.IsUSB3Camera == ASI_BOOL_ASI_TRUE as _).into(),
         );
+        info.insert(
+            "USB Speed".to_string(),
+            UsbSpeed::from_info(&value).to_string().into(),
+        );
+        if let Some(sensor) = sensor_model(&name) {
+            info.insert("Sensor Model".to_string(), sensor.to_string().into());
+        }
         info.insert(
             "Electrons per ADU".to_string(),
             (value.ElecPerADU as f64).into(),
@@ -181,8 +284,10 @@ pub fn get_control_value(handle: i32, control: AsiControlType) -> Result<(i64, i
     .map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        AsiError::InvalidControlType(_, _) => GenCamError::InvalidControlType(control.to_string()),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        AsiError::InvalidControlType(_, _) => {
+            GenCamError::InvalidControlType(with_code(control, &e))
+        }
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
     Ok((value as _, auto as _))
 }
@@ -200,8 +305,10 @@ pub fn set_control_value(
     ASICALL!(ASISetControlValue(handle, control, value, auto)).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        AsiError::InvalidControlType(_, _) => GenCamError::InvalidControlType(control.to_string()),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        AsiError::InvalidControlType(_, _) => {
+            GenCamError::InvalidControlType(with_code(control, &e))
+        }
+        _ => GenCamError::GeneralError(e.to_string()),
     })
 }
 
@@ -229,7 +336,24 @@ pub(crate) fn map_control_cap(
     obj: &ASI_CONTROL_CAPS,
 ) -> Option<(GenCamCtrl, (AsiControlType, Property))> {
     use AsiControlType::*;
-    match obj.ControlType.into() {
+    let ctype: AsiControlType = obj.ControlType.into();
+    // durations can not be negative
+    let unsigned = matches!(ctype, Exposure | AutoExpMax);
+    if obj.MinValue > obj.MaxValue
+        || obj.DefaultValue < obj.MinValue
+        || obj.DefaultValue > obj.MaxValue
+        || (unsigned && obj.MinValue < 0)
+    {
+        warn!(
+            "Skipping control {}: invalid range [{}, {}], default {}",
+            string_from_char(&obj.Name),
+            obj.MinValue,
+            obj.MaxValue,
+            obj.DefaultValue
+        );
+        return None;
+    }
+    match ctype {
         Gain => Some((
             AnalogCtrl::Gain.into(),
             (
@@ -403,6 +527,23 @@ pub(crate) fn map_control_cap(
                 ),
             ),
         )),
+        // percentage on some cameras, on (1)/off (0) on others
+        AntiDewHeater => Some((
+            DeviceCtrl::Custom("AntiDewHeater".into()).into(),
+            (
+                AntiDewHeater,
+                Property::new(
+                    PropertyLims::Int {
+                        min: obj.MinValue as _,
+                        max: obj.MaxValue as _,
+                        step: 1,
+                        default: obj.DefaultValue as _,
+                    },
+                    obj.IsAutoSupported == ASI_BOOL_ASI_TRUE as _,
+                    obj.IsWritable != ASI_BOOL_ASI_TRUE as _,
+                ),
+            ),
+        )),
         _ => None,
     }
 }
@@ -533,6 +674,15 @@ impl AsiDeviceCtrl {
                 control: *name,
                 error: PropertyError::NotFound,
             })?;
+        if name == &GenCamCtrl::Device(DeviceCtrl::Custom("UUID".into())) {
+            let handle = handle.handle();
+            let uuid = get_uuid(handle).map_err(|e| match e {
+                AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
+                AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
+                _ => GenCamError::GeneralError(e.to_string()),
+            })?;
+            return Ok((PropertyValue::EnumStr(uuid), false));
+        }
         let (value, auto) = get_control_value(handle.handle(), *ctrl)?;
         if name == &GenCamCtrl::Device(DeviceCtrl::Temperature) {
             Ok((PropertyValue::Float(value as f64 / 10.0), auto != 0))
@@ -541,24 +691,40 @@ impl AsiDeviceCtrl {
         }
     }
 
-    pub(crate) fn set_value(
+    /// Check that `value` can be written to the control `name`, without writing it.
+    pub(crate) fn check_value(
         &self,
-        handle: &AsiHandle,
         name: &GenCamCtrl,
         value: &PropertyValue,
-        auto: bool,
-    ) -> Result<(), GenCamError> {
+    ) -> Result<AsiControlType, GenCamError> {
         let (ctrl, prop) = self
             .get_controller(name)
             .ok_or(GenCamError::PropertyError {
                 control: *name,
                 error: PropertyError::NotFound,
             })?;
+        if prop.is_readonly() {
+            return Err(GenCamError::PropertyError {
+                control: *name,
+                error: PropertyError::ReadOnly,
+            });
+        }
         prop.validate(value)
             .map_err(|e| GenCamError::PropertyError {
                 control: *name,
                 error: e,
             })?;
+        Ok(*ctrl)
+    }
+
+    pub(crate) fn set_value(
+        &self,
+        handle: &AsiHandle,
+        name: &GenCamCtrl,
+        value: &PropertyValue,
+        auto: bool,
+    ) -> Result<(), GenCamError> {
+        let ctrl = self.check_value(name, value)?;
         let value = match value {
             PropertyValue::Int(v) => *v,
             PropertyValue::Float(v) => (*v * 10.0) as i64,
@@ -572,7 +738,7 @@ impl AsiDeviceCtrl {
                 })
             }
         };
-        set_control_value(handle.handle(), *ctrl, value, to_asibool(auto))
+        set_control_value(handle.handle(), ctrl, value, to_asibool(auto))
     }
 }
 
@@ -694,6 +860,7 @@ pub(crate) enum AsiControlType {
     Gamma = ASI_CONTROL_TYPE_ASI_GAMMA as _,
     WhiteBalR = ASI_CONTROL_TYPE_ASI_WB_R as _,
     WhiteBalB = ASI_CONTROL_TYPE_ASI_WB_B as _,
+    Offset = ASI_CONTROL_TYPE_ASI_OFFSET as _,
     BWOvld = ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD as _,
     Overclock = ASI_CONTROL_TYPE_ASI_OVERCLOCK as _,
     Temperature = ASI_CONTROL_TYPE_ASI_TEMPERATURE as _,
@@ -713,6 +880,24 @@ pub(crate) enum AsiControlType {
     Invalid,
 }
 
+impl AsiControlType {
+    /// Get the unit of the property built for this control by [`map_control_cap`].
+    pub(crate) fn unit(&self) -> ControlUnit {
+        use AsiControlType::*;
+        match self {
+            Gain => ControlUnit::Decibel,
+            Exposure | AutoExpMax => ControlUnit::Microseconds,
+            Temperature | TargetTemp => ControlUnit::Celsius,
+            BWOvld | CoolerPowerPercent => ControlUnit::Percent,
+            Gamma | WhiteBalR | WhiteBalB | Offset | Overclock | AutoExpTarget | AutoExpMaxGain
+            | PatternAdjust | AntiDewHeater => ControlUnit::Raw,
+            Flip | HardwareBin | HighSpeedMode | CoolerOn | MonoBin | FanOn | Invalid => {
+                ControlUnit::Unitless
+            }
+        }
+    }
+}
+
 impl From<u32> for AsiControlType {
     fn from(val: u32) -> Self {
         match val {
@@ -721,6 +906,7 @@ impl From<u32> for AsiControlType {
             ASI_CONTROL_TYPE_ASI_GAMMA => AsiControlType::Gamma,
             ASI_CONTROL_TYPE_ASI_WB_R => AsiControlType::WhiteBalR,
             ASI_CONTROL_TYPE_ASI_WB_B => AsiControlType::WhiteBalB,
+            ASI_CONTROL_TYPE_ASI_OFFSET => AsiControlType::Offset,
             ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD => AsiControlType::BWOvld,
             ASI_CONTROL_TYPE_ASI_OVERCLOCK => AsiControlType::Overclock,
             ASI_CONTROL_TYPE_ASI_TEMPERATURE => AsiControlType::Temperature,
@@ -824,18 +1010,153 @@ impl Display for AsiError {
             GeneralError(src, args) => ("General Error", src, args),
             InvalidMode(src, args) => ("Invalid Mode", src, args),
         };
-        if let Some(caller) = caller {
+        let msg = if let Some(caller) = caller {
             if let Some(args) = args {
-                write!(f, "{}({}): {}", caller, args, err)
+                format!("{}({}): {}", caller, args, err)
             } else {
-                write!(f, "{}(): {}", caller, err)
+                format!("{}(): {}", caller, err)
             }
         } else {
-            write!(f, "Operation: {}", err)
+            format!("Operation: {}", err)
+        };
+        f.write_str(&with_code(msg, self))
+    }
+}
+
+/// Marker preceding the SDK error code at the end of error messages.
+const CODE_MARKER: &str = " (ASI error ";
+
+/// Append the SDK error code of `err` to `msg`.
+///
+/// Every [`GenCamError`] message built from an [`AsiError`] ends with this suffix,
+/// which [`AsiErrorCode::from_error`] parses back.
+pub(crate) fn with_code(msg: impl Display, err: &AsiError) -> String {
+    format!("{}{}{})", msg, CODE_MARKER, err.code())
+}
+
+impl AsiError {
+    /// The `ASI_ERROR_CODE` returned by the SDK.
+    pub(crate) fn code(&self) -> u32 {
+        use AsiError::*;
+        match self {
+            InvalidIndex(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX,
+            InvalidId(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_ID,
+            InvalidControlType(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE,
+            CameraClosed(_, _) => ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED,
+            CameraRemoved(_, _) => ASI_ERROR_CODE_ASI_ERROR_CAMERA_REMOVED,
+            InvalidPath(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_PATH,
+            InvalidFileFormat(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_FILEFORMAT,
+            InvalidSize(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_SIZE,
+            InvalidImage(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE,
+            OutOfBounds(_, _) => ASI_ERROR_CODE_ASI_ERROR_OUTOF_BOUNDARY,
+            Timeout(_, _) => ASI_ERROR_CODE_ASI_ERROR_TIMEOUT,
+            InvalidSequence(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE,
+            BufferTooSmall(_, _) => ASI_ERROR_CODE_ASI_ERROR_BUFFER_TOO_SMALL,
+            VideoModeActive(_, _) => ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE,
+            ExposureInProgress(_, _) => ASI_ERROR_CODE_ASI_ERROR_EXPOSURE_IN_PROGRESS,
+            GeneralError(_, _) => ASI_ERROR_CODE_ASI_ERROR_GENERAL_ERROR,
+            InvalidMode(_, _) => ASI_ERROR_CODE_ASI_ERROR_INVALID_MODE,
         }
     }
 }
 
+/// Error codes of the ASI SDK.
+///
+/// SDK errors without an equivalent [`GenCamError`] are reported as
+/// [`GenCamError::GeneralError`], whose message ends with the numeric SDK code;
+/// [`AsiErrorCode::from_error`] recovers it.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AsiErrorCode {
+    /// No camera connected, or index out of bounds.
+    InvalidIndex = ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX,
+    /// Invalid camera ID.
+    InvalidId = ASI_ERROR_CODE_ASI_ERROR_INVALID_ID,
+    /// Invalid control type.
+    InvalidControlType = ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE,
+    /// Camera is not open.
+    CameraClosed = ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED,
+    /// Camera was removed.
+    CameraRemoved = ASI_ERROR_CODE_ASI_ERROR_CAMERA_REMOVED,
+    /// Path does not exist.
+    InvalidPath = ASI_ERROR_CODE_ASI_ERROR_INVALID_PATH,
+    /// Invalid file format.
+    InvalidFileFormat = ASI_ERROR_CODE_ASI_ERROR_INVALID_FILEFORMAT,
+    /// Invalid video format size.
+    InvalidSize = ASI_ERROR_CODE_ASI_ERROR_INVALID_SIZE,
+    /// Unsupported image type.
+    InvalidImageType = ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE,
+    /// Start position is out of bounds.
+    OutOfBounds = ASI_ERROR_CODE_ASI_ERROR_OUTOF_BOUNDARY,
+    /// Operation timed out.
+    Timeout = ASI_ERROR_CODE_ASI_ERROR_TIMEOUT,
+    /// Camera must be stopped first.
+    InvalidSequence = ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE,
+    /// Buffer is too small.
+    BufferTooSmall = ASI_ERROR_CODE_ASI_ERROR_BUFFER_TOO_SMALL,
+    /// Video capture is active.
+    VideoModeActive = ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE,
+    /// An exposure is in progress.
+    ExposureInProgress = ASI_ERROR_CODE_ASI_ERROR_EXPOSURE_IN_PROGRESS,
+    /// General error, e.g. a value out of range.
+    GeneralError = ASI_ERROR_CODE_ASI_ERROR_GENERAL_ERROR,
+    /// Invalid camera mode.
+    InvalidMode = ASI_ERROR_CODE_ASI_ERROR_INVALID_MODE,
+}
+
+impl AsiErrorCode {
+    /// Get the numeric `ASI_ERROR_CODE`.
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Get the SDK error code reported in `err`, if it was caused by an SDK error
+    /// mapped to a [`GenCamError`] that carries a message, e.g.
+    /// [`GenCamError::GeneralError`] or [`GenCamError::InvalidControlType`].
+    pub fn from_error(err: &GenCamError) -> Option<Self> {
+        let msg = match err {
+            GenCamError::GeneralError(msg)
+            | GenCamError::InvalidControlType(msg)
+            | GenCamError::InvalidImageType(msg)
+            | GenCamError::InvalidFormat(msg)
+            | GenCamError::ExposureFailed(msg) => msg,
+            _ => return None,
+        };
+        let (_, code) = msg.strip_suffix(')')?.rsplit_once(CODE_MARKER)?;
+        code.parse::<u32>().ok()?.try_into().ok()
+    }
+}
+
+impl TryFrom<u32> for AsiErrorCode {
+    type Error = u32;
+
+    /// Convert a numeric `ASI_ERROR_CODE`, returning it back if it is not an error code.
+    fn try_from(code: u32) -> Result<Self, u32> {
+        use AsiErrorCode::*;
+        Ok(match code {
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX => InvalidIndex,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_ID => InvalidId,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE => InvalidControlType,
+            ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED => CameraClosed,
+            ASI_ERROR_CODE_ASI_ERROR_CAMERA_REMOVED => CameraRemoved,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_PATH => InvalidPath,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_FILEFORMAT => InvalidFileFormat,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_SIZE => InvalidSize,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE => InvalidImageType,
+            ASI_ERROR_CODE_ASI_ERROR_OUTOF_BOUNDARY => OutOfBounds,
+            ASI_ERROR_CODE_ASI_ERROR_TIMEOUT => Timeout,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE => InvalidSequence,
+            ASI_ERROR_CODE_ASI_ERROR_BUFFER_TOO_SMALL => BufferTooSmall,
+            ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE => VideoModeActive,
+            ASI_ERROR_CODE_ASI_ERROR_EXPOSURE_IN_PROGRESS => ExposureInProgress,
+            ASI_ERROR_CODE_ASI_ERROR_GENERAL_ERROR => GeneralError,
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_MODE => InvalidMode,
+            _ => return Err(code),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AsiExposureStatus {
     Idle = ASI_EXPOSURE_STATUS_ASI_EXP_IDLE as _,
@@ -856,6 +1177,141 @@ impl From<ASI_EXPOSURE_STATUS> for AsiExposureStatus {
     }
 }
 
+/// Operating modes of trigger-capable ASI cameras.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsiCameraMode {
+    /// Free-running (untriggered) mode.
+    Normal = ASI_CAMERA_MODE_ASI_MODE_NORMAL as _,
+    /// Software edge trigger.
+    SoftEdge = ASI_CAMERA_MODE_ASI_MODE_TRIG_SOFT_EDGE as _,
+    /// Hardware trigger on the rising edge.
+    RiseEdge = ASI_CAMERA_MODE_ASI_MODE_TRIG_RISE_EDGE as _,
+    /// Hardware trigger on the falling edge.
+    FallEdge = ASI_CAMERA_MODE_ASI_MODE_TRIG_FALL_EDGE as _,
+    /// Software level trigger.
+    SoftLevel = ASI_CAMERA_MODE_ASI_MODE_TRIG_SOFT_LEVEL as _,
+    /// Hardware trigger, exposing while the input is high.
+    HighLevel = ASI_CAMERA_MODE_ASI_MODE_TRIG_HIGH_LEVEL as _,
+    /// Hardware trigger, exposing while the input is low.
+    LowLevel = ASI_CAMERA_MODE_ASI_MODE_TRIG_LOW_LEVEL as _,
+}
+
+impl TryFrom<ASI_CAMERA_MODE> for AsiCameraMode {
+    type Error = GenCamError;
+
+    fn try_from(val: ASI_CAMERA_MODE) -> Result<Self, Self::Error> {
+        match val {
+            ASI_CAMERA_MODE_ASI_MODE_NORMAL => Ok(AsiCameraMode::Normal),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_SOFT_EDGE => Ok(AsiCameraMode::SoftEdge),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_RISE_EDGE => Ok(AsiCameraMode::RiseEdge),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_FALL_EDGE => Ok(AsiCameraMode::FallEdge),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_SOFT_LEVEL => Ok(AsiCameraMode::SoftLevel),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_HIGH_LEVEL => Ok(AsiCameraMode::HighLevel),
+            ASI_CAMERA_MODE_ASI_MODE_TRIG_LOW_LEVEL => Ok(AsiCameraMode::LowLevel),
+            _ => Err(GenCamError::GeneralError(format!(
+                "ASI: Invalid camera mode: {}",
+                val
+            ))),
+        }
+    }
+}
+
+/// Directions for ST4 pulse guiding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GuideDir {
+    /// Guide towards celestial north.
+    North,
+    /// Guide towards celestial south.
+    South,
+    /// Guide towards celestial east.
+    East,
+    /// Guide towards celestial west.
+    West,
+}
+
+impl From<GuideDir> for ASI_GUIDE_DIRECTION {
+    fn from(val: GuideDir) -> Self {
+        match val {
+            GuideDir::North => ASI_GUIDE_DIRECTION_ASI_GUIDE_NORTH,
+            GuideDir::South => ASI_GUIDE_DIRECTION_ASI_GUIDE_SOUTH,
+            GuideDir::East => ASI_GUIDE_DIRECTION_ASI_GUIDE_EAST,
+            GuideDir::West => ASI_GUIDE_DIRECTION_ASI_GUIDE_WEST,
+        }
+    }
+}
+
+/// USB speed negotiated between a camera and the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UsbSpeed {
+    /// USB 2.0, either a USB2 camera or a USB3 camera on a USB2 port.
+    Usb2,
+    /// USB 3.0.
+    Usb3,
+    /// The SDK did not report the connection.
+    Unknown,
+}
+
+impl UsbSpeed {
+    /// Get the negotiated USB speed from the camera information.
+    pub(crate) fn from_info(info: &ASI_CAMERA_INFO) -> Self {
+        let flag = |v: ASI_BOOL| match v {
+            ASI_BOOL_ASI_TRUE => Some(true),
+            ASI_BOOL_ASI_FALSE => Some(false),
+            _ => None,
+        };
+        match (flag(info.IsUSB3Camera), flag(info.IsUSB3Host)) {
+            (Some(true), Some(true)) => UsbSpeed::Usb3,
+            (Some(false), _) | (Some(true), Some(false)) => UsbSpeed::Usb2,
+            _ => UsbSpeed::Unknown,
+        }
+    }
+
+    /// Check whether a USB3 camera is running at USB2 speed.
+    pub(crate) fn is_degraded(info: &ASI_CAMERA_INFO) -> bool {
+        info.IsUSB3Camera == ASI_BOOL_ASI_TRUE as _ && Self::from_info(info) == UsbSpeed::Usb2
+    }
+}
+
+impl Display for UsbSpeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsbSpeed::Usb2 => write!(f, "USB2"),
+            UsbSpeed::Usb3 => write!(f, "USB3"),
+            UsbSpeed::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// Unit of the value of a control, as exposed through its [`Property`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlUnit {
+    /// Time, exposed as a [`Duration`] with microsecond resolution.
+    Microseconds,
+    /// Degrees Celsius.
+    Celsius,
+    /// Percentage (0-100).
+    Percent,
+    /// Decibels.
+    Decibel,
+    /// Camera-specific units.
+    Raw,
+    /// On/off switches and selections, which have no unit.
+    Unitless,
+}
+
+impl Display for ControlUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlUnit::Microseconds => write!(f, "us"),
+            ControlUnit::Celsius => write!(f, "°C"),
+            ControlUnit::Percent => write!(f, "%"),
+            ControlUnit::Decibel => write!(f, "dB"),
+            ControlUnit::Raw | ControlUnit::Unitless => Ok(()),
+        }
+    }
+}
+
 pub(crate) fn to_asibool(v: bool) -> ASI_BOOL {
     if v {
         ASI_BOOL_ASI_TRUE
@@ -865,20 +1321,51 @@ pub(crate) fn to_asibool(v: bool) -> ASI_BOOL {
 }
 
 #[derive(Debug)]
-pub(crate) struct AsiHandle(i32);
+pub(crate) struct AsiHandle(
+    i32,        // id
+    AtomicBool, // turn the cooler off on close
+    AtomicBool, // reset to full frame on close
+);
 
 impl AsiHandle {
     pub(crate) fn handle(&self) -> i32 {
         self.0
     }
 
+    /// Set whether the cooler is turned off when the camera is closed.
+    pub(crate) fn set_cooler_management(&self, manage: bool) {
+        self.1.store(manage, Ordering::SeqCst);
+    }
+
+    /// Set whether the ROI is reset to the full frame when the camera is closed.
+    pub(crate) fn set_restore_roi(&self, restore: bool) {
+        self.2.store(restore, Ordering::SeqCst);
+    }
+
+    /// Reset to the full sensor at bin 1, keeping the current pixel format.
+    fn restore_full_frame(&self) -> Result<(), GenCamError> {
+        let handle = self.handle();
+        let info = get_info(handle)?;
+        let cur = AsiRoi::get(handle).map_err(|e| GenCamError::GeneralError(e.to_string()))?;
+        let full = AsiRoi {
+            x: 0,
+            y: 0,
+            width: info.MaxWidth as _,
+            height: info.MaxHeight as _,
+            bin: 1,
+            fmt: cur.fmt,
+        };
+        full.set(handle)
+            .map_err(|e| GenCamError::GeneralError(e.to_string()))
+    }
+
     pub(crate) fn state_raw(&self) -> Result<AsiExposureStatus, GenCamError> {
         let handle = self.handle();
         let mut stat = Default::default();
         ASICALL!(ASIGetExpStatus(handle, &mut stat)).map_err(|e| match e {
             AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
             AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-            _ => GenCamError::GeneralError(format!("{:?}", e)),
+            _ => GenCamError::GeneralError(e.to_string()),
         })?;
         Ok(stat.into())
     }
@@ -886,7 +1373,7 @@ impl AsiHandle {
 
 impl From<i32> for AsiHandle {
     fn from(val: i32) -> Self {
-        Self(val)
+        Self(val, AtomicBool::new(true), AtomicBool::new(false))
     }
 }
 
@@ -903,13 +1390,21 @@ impl Drop for AsiHandle {
             warn!("Failed to stop exposure: {:?}", e);
         }
 
-        if let Err(e) = ASICALL!(ASISetControlValue(
-            handle,
-            ASI_CONTROL_TYPE_ASI_COOLER_ON as i32,
-            0,
-            ASI_BOOL_ASI_FALSE as i32
-        )) {
-            warn!("Failed to turn off cooler: {:?}", e);
+        if self.2.load(Ordering::SeqCst) {
+            if let Err(e) = self.restore_full_frame() {
+                warn!("Failed to restore full frame ROI: {:?}", e);
+            }
+        }
+
+        if self.1.load(Ordering::SeqCst) {
+            if let Err(e) = ASICALL!(ASISetControlValue(
+                handle,
+                ASI_CONTROL_TYPE_ASI_COOLER_ON as i32,
+                0,
+                ASI_BOOL_ASI_FALSE as i32
+            )) {
+                warn!("Failed to turn off cooler: {:?}", e);
+            }
         }
 
         if let Err(e) = ASICALL!(ASICloseCamera(handle)) {
@@ -918,12 +1413,21 @@ impl Drop for AsiHandle {
     }
 }
 
+/// Get the UUID (8 bytes) of a USB3 camera.
+pub(crate) fn get_uuid(handle: i32) -> Result<String, AsiError> {
+    let mut id = ASI_ID::default();
+    ASICALL!(ASIGetID(handle, &mut id as _))?;
+    Ok(String::from_utf8_lossy(&id.id)
+        .trim_end_matches(char::from(0))
+        .to_string())
+}
+
 pub(crate) fn get_info(handle: i32) -> Result<ASI_CAMERA_INFO, GenCamError> {
     let mut info = ASI_CAMERA_INFO::default();
     ASICALL!(ASIGetCameraPropertyByID(handle, &mut info)).map_err(|e| match e {
         AsiError::CameraRemoved(_, _) => GenCamError::CameraRemoved,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
     Ok(info)
 }
@@ -933,7 +1437,7 @@ pub(crate) fn get_control_caps(handle: i32) -> Result<Vec<ASI_CONTROL_CAPS>, Gen
     ASICALL!(ASIGetNumOfControls(handle, &mut num_ctrl)).map_err(|e| match e {
         AsiError::CameraClosed(_, _) => GenCamError::CameraClosed,
         AsiError::InvalidId(_, _) => GenCamError::InvalidId(handle),
-        _ => GenCamError::GeneralError(format!("{:?}", e)),
+        _ => GenCamError::GeneralError(e.to_string()),
     })?;
     let mut caps = Vec::with_capacity(num_ctrl as _);
     for i in 0..num_ctrl {
@@ -963,3 +1467,287 @@ impl Display for ASI_CONTROL_CAPS {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zwo_sdk::mock;
+
+    const ERROR_CODES: [u32; 17] = [
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_ID,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE,
+        ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED,
+        ASI_ERROR_CODE_ASI_ERROR_CAMERA_REMOVED,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_PATH,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_FILEFORMAT,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_SIZE,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE,
+        ASI_ERROR_CODE_ASI_ERROR_OUTOF_BOUNDARY,
+        ASI_ERROR_CODE_ASI_ERROR_TIMEOUT,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_SEQUENCE,
+        ASI_ERROR_CODE_ASI_ERROR_BUFFER_TOO_SMALL,
+        ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE,
+        ASI_ERROR_CODE_ASI_ERROR_EXPOSURE_IN_PROGRESS,
+        ASI_ERROR_CODE_ASI_ERROR_GENERAL_ERROR,
+        ASI_ERROR_CODE_ASI_ERROR_INVALID_MODE,
+    ];
+
+    #[test]
+    fn error_codes_round_trip() {
+        for code in ERROR_CODES {
+            let err = AsiError::from((code, Some("ASIOpenCamera"), Some("0")));
+            assert_eq!(err.code(), code);
+            let kind = AsiErrorCode::try_from(code).expect("Not an error code");
+            assert_eq!(kind.code(), code);
+            let mapped = GenCamError::GeneralError(err.to_string());
+            assert_eq!(AsiErrorCode::from_error(&mapped), Some(kind));
+            // release builds do not record the call
+            let err = AsiError::from((code, None::<&str>, None));
+            let mapped = GenCamError::GeneralError(err.to_string());
+            assert_eq!(AsiErrorCode::from_error(&mapped), Some(kind));
+        }
+    }
+
+    #[test]
+    fn error_code_not_an_error() {
+        assert_eq!(
+            AsiErrorCode::try_from(ASI_ERROR_CODE_ASI_SUCCESS),
+            Err(ASI_ERROR_CODE_ASI_SUCCESS)
+        );
+        let err = GenCamError::GeneralError("Frame already downloaded".into());
+        assert_eq!(AsiErrorCode::from_error(&err), None);
+        assert_eq!(AsiErrorCode::from_error(&GenCamError::CameraClosed), None);
+    }
+
+    #[test]
+    fn error_code_in_other_variants() {
+        let _sdk = mock::install();
+        ASICALL!(ASIOpenCamera(mock::CAMERA_ID)).expect("Could not open camera");
+        // the mock has no gamma control
+        let err = set_control_value(
+            mock::CAMERA_ID,
+            AsiControlType::Gamma,
+            50,
+            ASI_BOOL_ASI_FALSE,
+        )
+        .expect_err("Gamma was set");
+        assert!(matches!(err, GenCamError::InvalidControlType(_)));
+        assert_eq!(
+            AsiErrorCode::from_error(&err),
+            Some(AsiErrorCode::InvalidControlType)
+        );
+        let err =
+            get_control_value(mock::CAMERA_ID, AsiControlType::Gamma).expect_err("Gamma was read");
+        assert_eq!(
+            AsiErrorCode::from_error(&err),
+            Some(AsiErrorCode::InvalidControlType)
+        );
+        let err = AsiError::from((
+            ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE,
+            Some("ASISetROIFormat"),
+            None,
+        ));
+        let mapped = GenCamError::InvalidImageType(err.to_string());
+        assert_eq!(
+            AsiErrorCode::from_error(&mapped),
+            Some(AsiErrorCode::InvalidImageType)
+        );
+    }
+
+    #[test]
+    fn with_code_suffix() {
+        let err = AsiError::from((
+            ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE,
+            Some("ASIStartExposure"),
+            Some("0, 0"),
+        ));
+        let code = ASI_ERROR_CODE_ASI_ERROR_VIDEO_MODE_ACTIVE;
+        assert_eq!(
+            err.to_string(),
+            format!("ASIStartExposure(0, 0): Video Mode Active (ASI error {code})")
+        );
+        let msg = with_code("Video capture is active", &err);
+        assert_eq!(msg, format!("Video capture is active (ASI error {code})"));
+        assert_eq!(
+            AsiErrorCode::from_error(&GenCamError::GeneralError(msg)),
+            Some(AsiErrorCode::VideoModeActive)
+        );
+    }
+
+    #[test]
+    fn sensor_model_known() {
+        assert_eq!(sensor_model("ZWO ASI533MC Pro"), Some("IMX533"));
+        assert_eq!(sensor_model("ZWO ASI120MM Mini"), Some("AR0130CS"));
+        assert_eq!(sensor_model("ZWO ASI6200MM Pro"), Some("IMX455"));
+    }
+
+    #[test]
+    fn sensor_model_unknown() {
+        assert_eq!(sensor_model("ZWO ASI999MC"), None);
+        // "120" is not the start of "1200"
+        assert_eq!(sensor_model("ZWO ASI1200MM"), None);
+        assert_eq!(sensor_model("QHY268M"), None);
+        assert_eq!(sensor_model(""), None);
+    }
+
+    #[test]
+    fn sensor_model_variants() {
+        assert_eq!(sensor_model("ZWO ASI294MC"), Some("IMX294"));
+        assert_eq!(sensor_model("ZWO ASI294MC Pro"), Some("IMX294"));
+        assert_eq!(sensor_model("ZWO ASI294MM Pro"), Some("IMX492"));
+    }
+
+    #[test]
+    fn sensor_models_specific_first() {
+        for (idx, (prefix, _)) in SENSOR_MODELS.iter().enumerate() {
+            assert!(
+                !SENSOR_MODELS[..idx]
+                    .iter()
+                    .any(|(earlier, _)| prefix.starts_with(earlier)),
+                "{} is shadowed by an earlier model",
+                prefix
+            );
+        }
+    }
+
+    fn caps(
+        ty: ASI_CONTROL_TYPE,
+        min: raw::c_long,
+        max: raw::c_long,
+        default: raw::c_long,
+    ) -> ASI_CONTROL_CAPS {
+        crate::zwo_sdk::mock::control(ty, "Control", min, max, default, false).0
+    }
+
+    #[test]
+    fn control_cap_valid_range() {
+        let (ctrl, (ctype, _)) =
+            map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 0, 300, 100)).unwrap();
+        assert_eq!(ctrl, AnalogCtrl::Gain.into());
+        assert_eq!(ctype, AsiControlType::Gain);
+        // a single value range is valid
+        assert!(map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 0, 0, 0)).is_some());
+        // signed controls can be negative
+        assert!(map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_TARGET_TEMP, -40, 30, -10)).is_some());
+    }
+
+    #[test]
+    fn control_cap_min_above_max() {
+        assert!(map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 300, 0, 100)).is_none());
+    }
+
+    #[test]
+    fn control_cap_default_out_of_range() {
+        assert!(map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 0, 300, 301)).is_none());
+        assert!(map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 0, 300, -1)).is_none());
+    }
+
+    #[test]
+    fn control_cap_negative_duration() {
+        for ty in [
+            ASI_CONTROL_TYPE_ASI_EXPOSURE,
+            ASI_CONTROL_TYPE_ASI_AUTO_MAX_EXP,
+        ] {
+            assert!(map_control_cap(&caps(ty, 32, 2_000_000_000, 10_000)).is_some());
+            assert!(map_control_cap(&caps(ty, -100, 2_000_000_000, -1)).is_none());
+            assert!(map_control_cap(&caps(ty, -100, 2_000_000_000, 10_000)).is_none());
+        }
+    }
+
+    #[test]
+    fn usb_speed_from_info() {
+        const INVALID: ASI_BOOL = 7;
+        let cases = [
+            (ASI_BOOL_ASI_TRUE, ASI_BOOL_ASI_TRUE, UsbSpeed::Usb3, false),
+            (ASI_BOOL_ASI_TRUE, ASI_BOOL_ASI_FALSE, UsbSpeed::Usb2, true),
+            (ASI_BOOL_ASI_FALSE, ASI_BOOL_ASI_TRUE, UsbSpeed::Usb2, false),
+            (
+                ASI_BOOL_ASI_FALSE,
+                ASI_BOOL_ASI_FALSE,
+                UsbSpeed::Usb2,
+                false,
+            ),
+            // a USB2 camera runs at USB2 speed whatever the host reports
+            (ASI_BOOL_ASI_FALSE, INVALID, UsbSpeed::Usb2, false),
+            (ASI_BOOL_ASI_TRUE, INVALID, UsbSpeed::Unknown, false),
+            (INVALID, ASI_BOOL_ASI_TRUE, UsbSpeed::Unknown, false),
+            (INVALID, INVALID, UsbSpeed::Unknown, false),
+        ];
+        for (camera, host, speed, degraded) in cases {
+            let info = ASI_CAMERA_INFO {
+                IsUSB3Camera: camera,
+                IsUSB3Host: host,
+                ..Default::default()
+            };
+            assert_eq!(
+                UsbSpeed::from_info(&info),
+                speed,
+                "camera {}, host {}",
+                camera,
+                host
+            );
+            assert_eq!(
+                UsbSpeed::is_degraded(&info),
+                degraded,
+                "camera {}, host {}",
+                camera,
+                host
+            );
+        }
+    }
+
+    #[test]
+    fn control_units() {
+        use AsiControlType::*;
+        let units = [
+            (Gain, ControlUnit::Decibel),
+            (Exposure, ControlUnit::Microseconds),
+            (AutoExpMax, ControlUnit::Microseconds),
+            (Temperature, ControlUnit::Celsius),
+            (TargetTemp, ControlUnit::Celsius),
+            (BWOvld, ControlUnit::Percent),
+            (CoolerPowerPercent, ControlUnit::Percent),
+            (Offset, ControlUnit::Raw),
+            (Gamma, ControlUnit::Raw),
+            (WhiteBalR, ControlUnit::Raw),
+            (WhiteBalB, ControlUnit::Raw),
+            (Overclock, ControlUnit::Raw),
+            (AutoExpTarget, ControlUnit::Raw),
+            (AutoExpMaxGain, ControlUnit::Raw),
+            (PatternAdjust, ControlUnit::Raw),
+            (AntiDewHeater, ControlUnit::Raw),
+            (Flip, ControlUnit::Unitless),
+            (HardwareBin, ControlUnit::Unitless),
+            (HighSpeedMode, ControlUnit::Unitless),
+            (CoolerOn, ControlUnit::Unitless),
+            (MonoBin, ControlUnit::Unitless),
+            (FanOn, ControlUnit::Unitless),
+            (Invalid, ControlUnit::Unitless),
+        ];
+        for (ctype, unit) in units {
+            assert_eq!(ctype.unit(), unit, "{:?}", ctype);
+        }
+        assert_eq!(AsiControlType::from(ASI_CONTROL_TYPE_ASI_OFFSET), Offset);
+    }
+
+    #[test]
+    fn control_values_in_units() {
+        // the sensor temperature is reported in 0.1 C
+        let (_, (_, prop)) =
+            map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_TEMPERATURE, -500, 1000, 200)).unwrap();
+        let min: f64 = prop.get_min().unwrap().try_into().unwrap();
+        let max: f64 = prop.get_max().unwrap().try_into().unwrap();
+        assert_eq!((min, max), (-50.0, 100.0));
+        // the target temperature is in whole degrees
+        let (_, (_, prop)) =
+            map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_TARGET_TEMP, -40, 30, 0)).unwrap();
+        let min: i64 = prop.get_min().unwrap().try_into().unwrap();
+        assert_eq!(min, -40);
+        // gain is reported in 0.1 dB
+        let (_, (_, prop)) =
+            map_control_cap(&caps(ASI_CONTROL_TYPE_ASI_GAIN, 0, 300, 100)).unwrap();
+        let max: f64 = prop.get_max().unwrap().try_into().unwrap();
+        assert_eq!(max, 30.0);
+    }
+}
@@ -0,0 +1,695 @@
+#![allow(non_snake_case)]
+//! Indirection over the ASI SDK functions.
+//!
+//! Every SDK call made with `ASICALL!` goes through the [`ZwoSdk`] currently
+//! installed, which is [`AsiSdk`], the ASICamera2 library. The tests install the
+//! simulated camera of the `mock` module instead, to drive the crate without a camera.
+use std::{
+    os::raw::{c_int, c_long, c_uchar},
+    sync::{PoisonError, RwLock},
+};
+
+use crate::zwo_ffi::{
+    self, ASI_BOOL, ASI_CAMERA_INFO, ASI_CAMERA_MODE, ASI_CONTROL_CAPS, ASI_CONTROL_TYPE,
+    ASI_ERROR_CODE, ASI_EXPOSURE_STATUS, ASI_GUIDE_DIRECTION, ASI_ID, ASI_IMG_TYPE, ASI_SN,
+    ASI_SUPPORTED_MODE,
+};
+
+/// Declare the [`ZwoSdk`] trait, and implement it for [`AsiSdk`] by forwarding
+/// each function to the SDK function of the same name.
+macro_rules! zwo_sdk {
+    ($($func:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        /// The ASI SDK functions used by the crate.
+        ///
+        /// Methods mirror the SDK functions of the same name.
+        ///
+        /// # Safety
+        /// Pointer arguments must be valid for the SDK function being called.
+        pub(crate) trait ZwoSdk: Send + Sync {
+            $(unsafe fn $func(&self, $($arg: $ty),*) -> $ret;)*
+        }
+
+        impl ZwoSdk for AsiSdk {
+            $(unsafe fn $func(&self, $($arg: $ty),*) -> $ret {
+                zwo_ffi::$func($($arg),*)
+            })*
+        }
+    };
+}
+
+/// The ASICamera2 library.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct AsiSdk;
+
+zwo_sdk! {
+    ASIGetNumOfConnectedCameras() -> c_int;
+    ASIGetCameraProperty(info: *mut ASI_CAMERA_INFO, index: c_int) -> ASI_ERROR_CODE;
+    ASIGetCameraPropertyByID(id: c_int, info: *mut ASI_CAMERA_INFO) -> ASI_ERROR_CODE;
+    ASIOpenCamera(id: c_int) -> ASI_ERROR_CODE;
+    ASIInitCamera(id: c_int) -> ASI_ERROR_CODE;
+    ASICloseCamera(id: c_int) -> ASI_ERROR_CODE;
+    ASIGetNumOfControls(id: c_int, num: *mut c_int) -> ASI_ERROR_CODE;
+    ASIGetControlCaps(id: c_int, index: c_int, caps: *mut ASI_CONTROL_CAPS) -> ASI_ERROR_CODE;
+    ASIGetControlValue(
+        id: c_int,
+        control: ASI_CONTROL_TYPE,
+        value: *mut c_long,
+        auto: *mut ASI_BOOL
+    ) -> ASI_ERROR_CODE;
+    ASISetControlValue(
+        id: c_int,
+        control: ASI_CONTROL_TYPE,
+        value: c_long,
+        auto: ASI_BOOL
+    ) -> ASI_ERROR_CODE;
+    ASISetROIFormat(
+        id: c_int,
+        width: c_int,
+        height: c_int,
+        bin: c_int,
+        fmt: ASI_IMG_TYPE
+    ) -> ASI_ERROR_CODE;
+    ASIGetROIFormat(
+        id: c_int,
+        width: *mut c_int,
+        height: *mut c_int,
+        bin: *mut c_int,
+        fmt: *mut ASI_IMG_TYPE
+    ) -> ASI_ERROR_CODE;
+    ASISetStartPos(id: c_int, x: c_int, y: c_int) -> ASI_ERROR_CODE;
+    ASIGetStartPos(id: c_int, x: *mut c_int, y: *mut c_int) -> ASI_ERROR_CODE;
+    ASIStartExposure(id: c_int, dark: ASI_BOOL) -> ASI_ERROR_CODE;
+    ASIStopExposure(id: c_int) -> ASI_ERROR_CODE;
+    ASIGetExpStatus(id: c_int, status: *mut ASI_EXPOSURE_STATUS) -> ASI_ERROR_CODE;
+    ASIGetDataAfterExp(id: c_int, buf: *mut c_uchar, size: c_long) -> ASI_ERROR_CODE;
+    ASIGetID(id: c_int, uuid: *mut ASI_ID) -> ASI_ERROR_CODE;
+    ASISetID(id: c_int, uuid: ASI_ID) -> ASI_ERROR_CODE;
+    ASIGetSerialNumber(id: c_int, sn: *mut ASI_SN) -> ASI_ERROR_CODE;
+    ASIGetCameraSupportMode(id: c_int, modes: *mut ASI_SUPPORTED_MODE) -> ASI_ERROR_CODE;
+    ASIGetCameraMode(id: c_int, mode: *mut ASI_CAMERA_MODE) -> ASI_ERROR_CODE;
+    ASISetCameraMode(id: c_int, mode: ASI_CAMERA_MODE) -> ASI_ERROR_CODE;
+    ASIPulseGuideOn(id: c_int, dir: ASI_GUIDE_DIRECTION) -> ASI_ERROR_CODE;
+    ASIPulseGuideOff(id: c_int, dir: ASI_GUIDE_DIRECTION) -> ASI_ERROR_CODE;
+}
+
+static SDK: RwLock<&'static dyn ZwoSdk> = RwLock::new(&AsiSdk);
+
+/// Get the SDK implementation in use.
+pub(crate) fn sdk() -> &'static dyn ZwoSdk {
+    *SDK.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Replace the SDK implementation with a mock, returning the previous one.
+#[cfg(test)]
+pub(crate) fn set_sdk(sdk: &'static dyn ZwoSdk) -> &'static dyn ZwoSdk {
+    let mut cur = SDK.write().unwrap_or_else(PoisonError::into_inner);
+    std::mem::replace(&mut *cur, sdk)
+}
+
+/// A simulated camera, to drive the crate without the ASICamera2 library.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::{
+        collections::HashMap,
+        ops::Deref,
+        os::raw::{c_char, c_int, c_long, c_uchar},
+        sync::{Mutex, MutexGuard, PoisonError},
+    };
+
+    use super::{set_sdk, ZwoSdk};
+    use crate::zwo_ffi::*;
+
+    /// Id of the simulated camera.
+    pub(crate) const CAMERA_ID: c_int = 0;
+    /// Sensor width of the simulated camera.
+    pub(crate) const WIDTH: c_int = 64;
+    /// Sensor height of the simulated camera.
+    pub(crate) const HEIGHT: c_int = 48;
+
+    const SUCCESS: ASI_ERROR_CODE = ASI_ERROR_CODE_ASI_SUCCESS;
+
+    /// A control of the simulated camera: caps, current value and auto flag.
+    pub(crate) type Control = (ASI_CONTROL_CAPS, c_long, ASI_BOOL);
+
+    /// State of the simulated camera.
+    #[derive(Debug)]
+    pub(crate) struct Camera {
+        pub(crate) info: ASI_CAMERA_INFO,
+        pub(crate) controls: Vec<Control>,
+        pub(crate) open: bool,
+        /// Width, height, bin and image type.
+        pub(crate) roi: (c_int, c_int, c_int, ASI_IMG_TYPE),
+        pub(crate) start: (c_int, c_int),
+        pub(crate) status: ASI_EXPOSURE_STATUS,
+        /// Status an exposure is in once it has been started.
+        pub(crate) outcome: ASI_EXPOSURE_STATUS,
+        pub(crate) mode: ASI_CAMERA_MODE,
+        /// UUID stored in the flash of the camera.
+        pub(crate) uuid: [u8; 8],
+        /// Value of the `i`-th pixel of the frames read out.
+        pub(crate) pixel: fn(usize) -> u16,
+        /// Errors returned instead of calling the function, by SDK function, in order.
+        pub(crate) errors: HashMap<&'static str, Vec<ASI_ERROR_CODE>>,
+        /// Number of calls, by SDK function.
+        pub(crate) calls: HashMap<&'static str, usize>,
+    }
+
+    fn name<const N: usize>(name: &str) -> [c_char; N] {
+        let mut out = [0; N];
+        out.iter_mut()
+            .zip(name.bytes())
+            .for_each(|(o, b)| *o = b as _);
+        out
+    }
+
+    /// A writable control, at its default value.
+    pub(crate) fn control(
+        ty: ASI_CONTROL_TYPE,
+        label: &str,
+        min: c_long,
+        max: c_long,
+        default: c_long,
+        auto: bool,
+    ) -> Control {
+        let caps = ASI_CONTROL_CAPS {
+            Name: name(label),
+            MaxValue: max,
+            MinValue: min,
+            DefaultValue: default,
+            IsAutoSupported: if auto {
+                ASI_BOOL_ASI_TRUE
+            } else {
+                ASI_BOOL_ASI_FALSE
+            },
+            ControlType: ty,
+            IsWritable: ASI_BOOL_ASI_TRUE,
+            ..Default::default()
+        };
+        (caps, default, ASI_BOOL_ASI_FALSE)
+    }
+
+    impl Default for Camera {
+        /// A 64x48 mono camera with a cooler, supporting RAW8 and RAW16 at bin 1 and 2.
+        fn default() -> Self {
+            let mut info = ASI_CAMERA_INFO {
+                Name: name("ZWO ASI120MM Mini"),
+                CameraID: CAMERA_ID,
+                MaxHeight: HEIGHT as _,
+                MaxWidth: WIDTH as _,
+                IsColorCam: ASI_BOOL_ASI_FALSE,
+                PixelSize: 3.75,
+                ST4Port: ASI_BOOL_ASI_TRUE,
+                IsCoolerCam: ASI_BOOL_ASI_TRUE,
+                ElecPerADU: 4.0,
+                BitDepth: 12,
+                ..Default::default()
+            };
+            info.SupportedBins[..2].copy_from_slice(&[1, 2]);
+            info.SupportedVideoFormat = [ASI_IMG_TYPE_ASI_IMG_END; 8];
+            info.SupportedVideoFormat[..2]
+                .copy_from_slice(&[ASI_IMG_TYPE_ASI_IMG_RAW8, ASI_IMG_TYPE_ASI_IMG_RAW16]);
+            let mut temperature = control(
+                ASI_CONTROL_TYPE_ASI_TEMPERATURE,
+                "Temperature",
+                -500,
+                1000,
+                200,
+                false,
+            );
+            temperature.0.IsWritable = ASI_BOOL_ASI_FALSE;
+            let controls = vec![
+                control(ASI_CONTROL_TYPE_ASI_GAIN, "Gain", 0, 300, 100, true),
+                control(
+                    ASI_CONTROL_TYPE_ASI_EXPOSURE,
+                    "Exposure",
+                    32,
+                    2_000_000_000,
+                    10_000,
+                    true,
+                ),
+                control(ASI_CONTROL_TYPE_ASI_OFFSET, "Offset", 0, 80, 10, false),
+                control(
+                    ASI_CONTROL_TYPE_ASI_BANDWIDTHOVERLOAD,
+                    "BandWidth",
+                    40,
+                    100,
+                    50,
+                    true,
+                ),
+                control(ASI_CONTROL_TYPE_ASI_FLIP, "Flip", 0, 3, 0, false),
+                control(
+                    ASI_CONTROL_TYPE_ASI_AUTO_TARGET_BRIGHTNESS,
+                    "AutoExpTargetBrightness",
+                    50,
+                    160,
+                    100,
+                    false,
+                ),
+                temperature,
+                control(
+                    ASI_CONTROL_TYPE_ASI_TARGET_TEMP,
+                    "TargetTemp",
+                    -40,
+                    30,
+                    0,
+                    false,
+                ),
+                control(ASI_CONTROL_TYPE_ASI_COOLER_ON, "CoolerOn", 0, 1, 0, false),
+                control(
+                    ASI_CONTROL_TYPE_ASI_COOLER_POWER_PERC,
+                    "CoolerPowerPerc",
+                    0,
+                    100,
+                    0,
+                    false,
+                ),
+            ];
+            Self {
+                info,
+                controls,
+                open: false,
+                roi: (WIDTH, HEIGHT, 1, ASI_IMG_TYPE_ASI_IMG_RAW8),
+                start: (0, 0),
+                status: ASI_EXPOSURE_STATUS_ASI_EXP_IDLE,
+                outcome: ASI_EXPOSURE_STATUS_ASI_EXP_SUCCESS,
+                mode: ASI_CAMERA_MODE_ASI_MODE_NORMAL,
+                uuid: *b"MOCKUUID",
+                pixel: |i| i as u16,
+                errors: HashMap::new(),
+                calls: HashMap::new(),
+            }
+        }
+    }
+
+    impl Camera {
+        fn control(&mut self, ty: ASI_CONTROL_TYPE) -> Option<&mut Control> {
+            self.controls
+                .iter_mut()
+                .find(|(caps, _, _)| caps.ControlType == ty)
+        }
+
+        /// Get the current value of a control.
+        pub(crate) fn value(&self, ty: ASI_CONTROL_TYPE) -> Option<c_long> {
+            self.controls
+                .iter()
+                .find(|(caps, _, _)| caps.ControlType == ty)
+                .map(|(_, value, _)| *value)
+        }
+
+        /// Change the value of a control, e.g. to simulate a sensor reading.
+        pub(crate) fn set_value(&mut self, ty: ASI_CONTROL_TYPE, value: c_long) {
+            if let Some((_, val, _)) = self.control(ty) {
+                *val = value;
+            }
+        }
+
+        fn sensor(&self) -> (c_int, c_int) {
+            (self.info.MaxWidth as _, self.info.MaxHeight as _)
+        }
+    }
+
+    /// The SDK of a single simulated camera, with id [`CAMERA_ID`].
+    #[derive(Debug, Default)]
+    pub(crate) struct MockSdk(Mutex<Camera>);
+
+    impl MockSdk {
+        /// Get the state of the camera.
+        pub(crate) fn camera(&self) -> MutexGuard<'_, Camera> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Make the next call to `func` fail with `code`.
+        pub(crate) fn fail(&self, func: &'static str, code: ASI_ERROR_CODE) {
+            self.camera().errors.entry(func).or_default().push(code);
+        }
+
+        /// Get the number of calls made to `func`.
+        pub(crate) fn calls(&self, func: &str) -> usize {
+            self.camera().calls.get(func).copied().unwrap_or(0)
+        }
+
+        /// Count the call to `func`, and run `f` unless an error was injected, or
+        /// the camera `id` does not exist or is closed.
+        fn call(
+            &self,
+            func: &'static str,
+            id: c_int,
+            f: impl FnOnce(&mut Camera) -> ASI_ERROR_CODE,
+        ) -> ASI_ERROR_CODE {
+            let mut cam = self.camera();
+            *cam.calls.entry(func).or_default() += 1;
+            if let Some(errors) = cam.errors.get_mut(func) {
+                if !errors.is_empty() {
+                    return errors.remove(0);
+                }
+            }
+            if id != CAMERA_ID {
+                return ASI_ERROR_CODE_ASI_ERROR_INVALID_ID;
+            }
+            let closed_ok = matches!(
+                func,
+                "ASIGetCameraProperty" | "ASIGetCameraPropertyByID" | "ASIOpenCamera"
+            );
+            if !cam.open && !closed_ok {
+                return ASI_ERROR_CODE_ASI_ERROR_CAMERA_CLOSED;
+            }
+            f(&mut *cam)
+        }
+    }
+
+    impl ZwoSdk for MockSdk {
+        unsafe fn ASIGetNumOfConnectedCameras(&self) -> c_int {
+            1
+        }
+
+        unsafe fn ASIGetCameraProperty(
+            &self,
+            info: *mut ASI_CAMERA_INFO,
+            index: c_int,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetCameraProperty", CAMERA_ID, |cam| {
+                if index != 0 {
+                    return ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX;
+                }
+                *info = cam.info;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetCameraPropertyByID(
+            &self,
+            id: c_int,
+            info: *mut ASI_CAMERA_INFO,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetCameraPropertyByID", id, |cam| {
+                *info = cam.info;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIOpenCamera(&self, id: c_int) -> ASI_ERROR_CODE {
+            self.call("ASIOpenCamera", id, |cam| {
+                cam.open = true;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIInitCamera(&self, id: c_int) -> ASI_ERROR_CODE {
+            self.call("ASIInitCamera", id, |_| SUCCESS)
+        }
+
+        unsafe fn ASICloseCamera(&self, id: c_int) -> ASI_ERROR_CODE {
+            self.call("ASICloseCamera", id, |cam| {
+                cam.open = false;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetNumOfControls(&self, id: c_int, num: *mut c_int) -> ASI_ERROR_CODE {
+            self.call("ASIGetNumOfControls", id, |cam| {
+                *num = cam.controls.len() as _;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetControlCaps(
+            &self,
+            id: c_int,
+            index: c_int,
+            caps: *mut ASI_CONTROL_CAPS,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetControlCaps", id, |cam| {
+                match usize::try_from(index)
+                    .ok()
+                    .and_then(|i| cam.controls.get(i))
+                {
+                    Some((ctrl, _, _)) => {
+                        *caps = *ctrl;
+                        SUCCESS
+                    }
+                    None => ASI_ERROR_CODE_ASI_ERROR_INVALID_INDEX,
+                }
+            })
+        }
+
+        unsafe fn ASIGetControlValue(
+            &self,
+            id: c_int,
+            control: ASI_CONTROL_TYPE,
+            value: *mut c_long,
+            auto: *mut ASI_BOOL,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetControlValue", id, |cam| match cam.control(control) {
+                Some((_, cur, cur_auto)) => {
+                    *value = *cur;
+                    *auto = *cur_auto;
+                    SUCCESS
+                }
+                None => ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE,
+            })
+        }
+
+        unsafe fn ASISetControlValue(
+            &self,
+            id: c_int,
+            control: ASI_CONTROL_TYPE,
+            value: c_long,
+            auto: ASI_BOOL,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASISetControlValue", id, |cam| match cam.control(control) {
+                Some((caps, cur, cur_auto)) => {
+                    // like the SDK, clamp to the range of the control
+                    *cur = value.clamp(caps.MinValue, caps.MaxValue);
+                    *cur_auto = auto;
+                    SUCCESS
+                }
+                None => ASI_ERROR_CODE_ASI_ERROR_INVALID_CONTROL_TYPE,
+            })
+        }
+
+        unsafe fn ASISetROIFormat(
+            &self,
+            id: c_int,
+            width: c_int,
+            height: c_int,
+            bin: c_int,
+            fmt: ASI_IMG_TYPE,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASISetROIFormat", id, |cam| {
+                let (max_width, max_height) = cam.sensor();
+                if bin <= 0
+                    || !cam.info.SupportedBins.contains(&bin)
+                    || width <= 0
+                    || height <= 0
+                    || width % 8 != 0
+                    || height % 2 != 0
+                    || width * bin > max_width
+                    || height * bin > max_height
+                {
+                    return ASI_ERROR_CODE_ASI_ERROR_INVALID_SIZE;
+                }
+                if !cam.info.SupportedVideoFormat.contains(&fmt) {
+                    return ASI_ERROR_CODE_ASI_ERROR_INVALID_IMGTYPE;
+                }
+                cam.roi = (width, height, bin, fmt);
+                // the SDK centers the new window
+                cam.start = (
+                    (max_width / bin - width) / 2,
+                    (max_height / bin - height) / 2,
+                );
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetROIFormat(
+            &self,
+            id: c_int,
+            width: *mut c_int,
+            height: *mut c_int,
+            bin: *mut c_int,
+            fmt: *mut ASI_IMG_TYPE,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetROIFormat", id, |cam| {
+                (*width, *height, *bin, *fmt) = cam.roi;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASISetStartPos(&self, id: c_int, x: c_int, y: c_int) -> ASI_ERROR_CODE {
+            self.call("ASISetStartPos", id, |cam| {
+                let (max_width, max_height) = cam.sensor();
+                let (width, height, bin, _) = cam.roi;
+                if x < 0 || y < 0 || x + width > max_width / bin || y + height > max_height / bin {
+                    return ASI_ERROR_CODE_ASI_ERROR_OUTOF_BOUNDARY;
+                }
+                cam.start = (x, y);
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetStartPos(&self, id: c_int, x: *mut c_int, y: *mut c_int) -> ASI_ERROR_CODE {
+            self.call("ASIGetStartPos", id, |cam| {
+                (*x, *y) = cam.start;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIStartExposure(&self, id: c_int, _dark: ASI_BOOL) -> ASI_ERROR_CODE {
+            self.call("ASIStartExposure", id, |cam| {
+                if cam.status == ASI_EXPOSURE_STATUS_ASI_EXP_WORKING {
+                    return ASI_ERROR_CODE_ASI_ERROR_EXPOSURE_IN_PROGRESS;
+                }
+                cam.status = cam.outcome;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIStopExposure(&self, id: c_int) -> ASI_ERROR_CODE {
+            self.call("ASIStopExposure", id, |cam| {
+                if cam.status == ASI_EXPOSURE_STATUS_ASI_EXP_WORKING {
+                    cam.status = ASI_EXPOSURE_STATUS_ASI_EXP_FAILED;
+                }
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetExpStatus(
+            &self,
+            id: c_int,
+            status: *mut ASI_EXPOSURE_STATUS,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetExpStatus", id, |cam| {
+                *status = cam.status;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetDataAfterExp(
+            &self,
+            id: c_int,
+            buf: *mut c_uchar,
+            size: c_long,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetDataAfterExp", id, |cam| {
+                if cam.status != ASI_EXPOSURE_STATUS_ASI_EXP_SUCCESS {
+                    return ASI_ERROR_CODE_ASI_ERROR_GENERAL_ERROR;
+                }
+                let (width, height, _, fmt) = cam.roi;
+                let raw16 = fmt == ASI_IMG_TYPE_ASI_IMG_RAW16;
+                let len = width as usize * height as usize * if raw16 { 2 } else { 1 };
+                if size < 0 || (size as usize) < len {
+                    return ASI_ERROR_CODE_ASI_ERROR_BUFFER_TOO_SMALL;
+                }
+                let buf = std::slice::from_raw_parts_mut(buf, len);
+                if raw16 {
+                    buf.chunks_exact_mut(2)
+                        .enumerate()
+                        .for_each(|(i, px)| px.copy_from_slice(&(cam.pixel)(i).to_ne_bytes()));
+                } else {
+                    buf.iter_mut()
+                        .enumerate()
+                        .for_each(|(i, px)| *px = (cam.pixel)(i) as u8);
+                }
+                cam.status = ASI_EXPOSURE_STATUS_ASI_EXP_IDLE;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetID(&self, id: c_int, uuid: *mut ASI_ID) -> ASI_ERROR_CODE {
+            self.call("ASIGetID", id, |cam| {
+                (*uuid).id = cam.uuid;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASISetID(&self, id: c_int, uuid: ASI_ID) -> ASI_ERROR_CODE {
+            self.call("ASISetID", id, |cam| {
+                cam.uuid = uuid.id;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetSerialNumber(&self, id: c_int, sn: *mut ASI_SN) -> ASI_ERROR_CODE {
+            self.call("ASIGetSerialNumber", id, |_| {
+                (*sn).id = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetCameraSupportMode(
+            &self,
+            id: c_int,
+            modes: *mut ASI_SUPPORTED_MODE,
+        ) -> ASI_ERROR_CODE {
+            self.call("ASIGetCameraSupportMode", id, |_| {
+                *modes = ASI_SUPPORTED_MODE::default();
+                (*modes).SupportedCameraMode[..2].copy_from_slice(&[
+                    ASI_CAMERA_MODE_ASI_MODE_NORMAL,
+                    ASI_CAMERA_MODE_ASI_MODE_TRIG_SOFT_EDGE,
+                ]);
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIGetCameraMode(&self, id: c_int, mode: *mut ASI_CAMERA_MODE) -> ASI_ERROR_CODE {
+            self.call("ASIGetCameraMode", id, |cam| {
+                *mode = cam.mode;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASISetCameraMode(&self, id: c_int, mode: ASI_CAMERA_MODE) -> ASI_ERROR_CODE {
+            self.call("ASISetCameraMode", id, |cam| {
+                cam.mode = mode;
+                SUCCESS
+            })
+        }
+
+        unsafe fn ASIPulseGuideOn(&self, id: c_int, _dir: ASI_GUIDE_DIRECTION) -> ASI_ERROR_CODE {
+            self.call("ASIPulseGuideOn", id, |_| SUCCESS)
+        }
+
+        unsafe fn ASIPulseGuideOff(&self, id: c_int, _dir: ASI_GUIDE_DIRECTION) -> ASI_ERROR_CODE {
+            self.call("ASIPulseGuideOff", id, |_| SUCCESS)
+        }
+    }
+
+    /// Serializes the tests using the mock, since the SDK is global.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// The installed mock; the previous SDK is restored when it is dropped.
+    ///
+    /// Cameras opened through the mock must be dropped before the guard.
+    pub(crate) struct MockGuard {
+        sdk: &'static MockSdk,
+        prev: &'static dyn ZwoSdk,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Deref for MockGuard {
+        type Target = MockSdk;
+
+        fn deref(&self) -> &MockSdk {
+            self.sdk
+        }
+    }
+
+    impl Drop for MockGuard {
+        fn drop(&mut self) {
+            set_sdk(self.prev);
+        }
+    }
+
+    /// Install a fresh simulated camera as the SDK.
+    pub(crate) fn install() -> MockGuard {
+        let lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        // leaked, since the SDK must be 'static; tests only create a few of these
+        let sdk: &'static MockSdk = Box::leak(Box::<MockSdk>::default());
+        let prev = set_sdk(sdk);
+        MockGuard {
+            sdk,
+            prev,
+            _lock: lock,
+        }
+    }
+}